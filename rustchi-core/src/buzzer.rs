@@ -0,0 +1,31 @@
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    memory::{REG_BZSHOT_ENVRST_ENVRT_ENVON, REG_R43_R42_R41_R40, REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0},
+    peripheral::Peripheral,
+};
+
+/// Buzzer output and envelope registers. Nothing is played yet, the ROM only sees its own writes.
+#[derive(Clone, Default)]
+pub struct Buzzer {}
+
+impl Buzzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Peripheral for Buzzer {
+    fn handles(&self, addr: usize) -> bool {
+        matches!(addr, REG_R43_R42_R41_R40 | REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0 | REG_BZSHOT_ENVRST_ENVRT_ENVON)
+    }
+
+    // TODO: buzzer
+    fn write(&mut self, _addr: usize, _value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,108 @@
+use std::ops::Range;
+
+use crate::{map::{Region, IO}, peripheral::Bus};
+
+// Display RAM nibble (pair) to segment, shared by the E0C6S46 and E0C6S48.
+const E0C6S46_SEGMENT_ORDER: [usize; 40] = [0, 1, 2, 3, 4, 5, 6, 7, 32, 8, 9, 10, 11, 12 ,13 ,14, 15, 33, 34, 35, 31, 30, 29, 28, 27, 26, 25, 24, 36, 23, 22, 21, 20, 19, 18, 17, 16, 37, 38, 39];
+
+/// Memory layout and hardware of a CPU of the E0C62 family.
+#[derive(Debug, Clone)]
+pub struct ChipProfile {
+    pub name: &'static str,
+    /// Program memory size in 12-bit words, stored in dumps in one of the `RomFormat`s.
+    pub rom_words: usize,
+    pub ram: Range<usize>,
+    /// Display RAM areas, each driving 8 COMs, first area first.
+    pub display: &'static [Range<usize>],
+    /// Segment driven by each pair of display RAM nibbles.
+    pub segment_order: &'static [usize],
+    pub peripherals: fn(&ChipProfile) -> Bus,
+}
+
+impl ChipProfile {
+    /// The E0C6S46 of the P1 and P2.
+    pub fn e0c6s46() -> Self {
+        Self {
+            name: "E0C6S46",
+            rom_words: 6144,
+            ram: 0x000..0x280,
+            display: &[0xE00..0xE50, 0xE80..0xED0],
+            segment_order: &E0C6S46_SEGMENT_ORDER,
+            peripherals: Bus::e0c6s46,
+        }
+    }
+
+    /// Same core and peripherals as the E0C6S46, with 8192 words of program memory and more RAM.
+    pub fn e0c6s48() -> Self {
+        Self {
+            name: "E0C6S48",
+            rom_words: 8192,
+            ram: 0x000..0x300,
+            ..Self::e0c6s46()
+        }
+    }
+
+    pub fn all() -> Vec<Self> {
+        vec![Self::e0c6s46(), Self::e0c6s48()]
+    }
+
+    /// Looks a profile up by chip name, ignoring case.
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|profile| profile.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn rom_bytes(&self) -> usize {
+        self.rom_words * 2
+    }
+
+    pub fn region(&self, addr: usize) -> Region {
+        match addr {
+            _ if self.ram.contains(&addr) => Region::Ram,
+            _ if self.display.iter().any(|area| area.contains(&addr)) => Region::Display,
+            _ if IO.contains(&addr) => Region::Io,
+            _ => Region::Unmapped,
+        }
+    }
+
+    /// COM and segment of the lowest bit of a display RAM nibble. The other bits drive the next COMs.
+    pub fn display_position(&self, addr: usize) -> Option<(usize, usize)> {
+        let (area, offset) = self.display.iter().enumerate()
+            .find_map(|(i, area)| area.contains(&addr).then(|| (i, addr - area.start)))?;
+        let segment = *self.segment_order.get(offset >> 1)?;
+        Some((area * 8 + (offset % 2) * 4, segment))
+    }
+}
+
+impl Default for ChipProfile {
+    fn default() -> Self {
+        Self::e0c6s46()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profiles() {
+        let s46 = ChipProfile::e0c6s46();
+        assert_eq!(s46.rom_bytes(), 12288);
+        assert_eq!(s46.region(0x27F), Region::Ram);
+        assert_eq!(s46.region(0x280), Region::Unmapped);
+        assert_eq!(s46.display_position(0xE00), Some((0, 0)));
+        assert_eq!(s46.display_position(0xE11), Some((4, 32)));
+        assert_eq!(s46.display_position(0xE80), Some((8, 0)));
+        assert_eq!(s46.display_position(0xE50), None);
+
+        let s48 = ChipProfile::by_name("e0c6s48").unwrap();
+        assert_eq!(s48.region(0x280), Region::Ram);
+        assert_eq!(s48.rom_bytes(), 16384);
+        assert!(ChipProfile::by_name("E0C6S4X").is_none());
+    }
+
+    #[test]
+    fn p1_dump_size() {
+        let rom = crate::rom::Rom::load(&[0x00; 12288], &ChipProfile::e0c6s46()).unwrap();
+        assert_eq!(rom.words.len(), 6144);
+    }
+}
@@ -1,9 +1,32 @@
 mod macros;
 
+pub mod buzzer;
 pub mod change;
+pub mod chip;
+pub mod error;
 pub mod interpreter;
 pub mod primitive;
+pub mod frame;
+pub mod golden;
+pub mod icons;
 pub mod input;
+pub mod interrupt;
+pub mod lcd;
+pub mod map;
+pub mod movie;
+pub mod osc;
+pub mod peripheral;
+pub mod persistence;
+pub mod rom;
+pub mod savestate;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod serial;
+pub mod session;
+pub mod stopwatch;
+pub mod svd;
+pub mod timer;
+pub mod watchdog;
 
 mod prelude;
 
@@ -16,3 +39,6 @@ mod opcode;
 mod registers;
 mod rq;
 mod state;
+
+#[cfg(test)]
+mod fixture;
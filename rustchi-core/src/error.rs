@@ -0,0 +1,47 @@
+use std::fmt;
+
+use crate::primitive::u4;
+
+/// Something the ROM did that the emulator doesn't model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulationError {
+    UnknownIoRead { addr: usize },
+    UnknownIoWrite { addr: usize, value: u4 },
+    /// A register value selecting hardware behaviour that isn't emulated.
+    UnsupportedIoValue { addr: usize, value: u4, reason: &'static str },
+    UnimplementedOpcode { pc: usize, word: u16 },
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulationError::UnknownIoRead { addr } =>
+                write!(f, "read from unknown I/O register {:#05X}", addr),
+            EmulationError::UnknownIoWrite { addr, value } =>
+                write!(f, "write of {:#X} to unknown I/O register {:#05X}", value, addr),
+            EmulationError::UnsupportedIoValue { addr, value, reason } =>
+                write!(f, "unsupported value {:#X} written to {:#05X}: {}", value, addr, reason),
+            EmulationError::UnimplementedOpcode { pc, word } =>
+                write!(f, "unimplemented opcode {:04X} at {:#06X}", word, pc),
+        }
+    }
+}
+
+impl std::error::Error for EmulationError {}
+
+/// What to do when the ROM does something unexpected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop and return the error from `step()`.
+    #[default]
+    Strict,
+    /// Log the error and carry on, ignoring the access or skipping the opcode.
+    Lenient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    /// The CPU is in HALT, only the timers ran.
+    Halted,
+}
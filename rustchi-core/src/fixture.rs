@@ -0,0 +1,13 @@
+use crate::{chip::ChipProfile, interpreter::Interpreter};
+
+/// A dump of `program` at the reset address 0x100, padded to fill the chip's program memory.
+pub(crate) fn rom(profile: &ChipProfile, program: &[u16]) -> Vec<u8> {
+    let mut words = vec![0xFFF_u16; profile.rom_words];
+    words[0x100..0x100 + program.len()].copy_from_slice(program);
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+/// `LD A,5 ; loop: INC M0 ; LDPX MX,3 ; JP loop`
+pub(crate) fn counter(profile: ChipProfile) -> Interpreter {
+    Interpreter::load(&rom(&profile, &[0xE05, 0xF60, 0xE63, 0x001]), profile).unwrap()
+}
@@ -0,0 +1,67 @@
+use crate::{icons::Icons, memory::Memory};
+
+pub const FRAME_WIDTH: usize = 32;
+pub const FRAME_HEIGHT: usize = 16;
+
+/// What the LCD shows, independent of the display RAM layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Darkness of each dot of the matrix over the frame, from 0 to 255, indexed by `[y][x]`.
+    pub dots: [[u8; FRAME_WIDTH]; FRAME_HEIGHT],
+    pub icons: Icons,
+    /// Darkness of each icon, in the order of the `Icons` flags.
+    pub icon_intensity: [u8; 8],
+    /// Whether anything changed since the previous frame was taken.
+    pub dirty: bool,
+}
+
+impl Frame {
+    /// Ends the current frame, averaging the picture over the cycles that ran since the previous one.
+    pub(crate) fn from_memory(memory: &mut Memory) -> Self {
+        let intensity = memory.take_lcd_frame();
+
+        Self {
+            dots: std::array::from_fn(|y| std::array::from_fn(|x| intensity[y][x])),
+            icons: memory.icons(),
+            icon_intensity: std::array::from_fn(|i| {
+                let (com, seg) = Icons::from_bits_retain(1 << i).dot();
+                intensity[com][seg]
+            }),
+            dirty: true,
+        }
+    }
+
+    pub fn is_on(&self, x: usize, y: usize) -> bool {
+        self.dots[y][x] >= 0x80
+    }
+
+    pub fn intensity(&self, x: usize, y: usize) -> u8 {
+        self.dots[y][x]
+    }
+
+    /// Darkness of a single icon.
+    pub fn icon(&self, icon: Icons) -> u8 {
+        self.icon_intensity[icon.bits().trailing_zeros() as usize]
+    }
+
+    /// Icons dark enough to be seen.
+    pub fn lit_icons(&self) -> Icons {
+        Icons::all().iter().filter(|icon| self.icon(*icon) >= 0x80).collect()
+    }
+
+    /// One line per row of the matrix, `#` for lit dots and `.` for the others, followed by the lit icons.
+    pub fn to_text_art(&self) -> String {
+        let mut text = String::new();
+        for y in 0..FRAME_HEIGHT {
+            text.extend((0..FRAME_WIDTH).map(|x| if self.is_on(x, y) { '#' } else { '.' }));
+            text.push('\n');
+        }
+        let icons: Vec<&str> = self.lit_icons().iter_names().map(|(name, _)| name).collect();
+        text.push_str(&format!("icons: {}\n", icons.join(" ")));
+        text
+    }
+
+    pub(crate) fn same_picture(&self, other: &Self) -> bool {
+        self.dots == other.dots && self.icons == other.icons && self.icon_intensity == other.icon_intensity
+    }
+}
@@ -0,0 +1,228 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+
+use crate::{
+    chip::ChipProfile,
+    error::EmulationError,
+    input::Button,
+    interpreter::Interpreter,
+    rom::RomError,
+    session::{Session, SessionError},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Press(Button),
+    Release(Button),
+    /// Compares the LCD with the golden named after the checkpoint.
+    Check(String),
+}
+
+/// Button presses and checkpoints at given cycle counts.
+///
+/// One event per line, `<cycles> press <button>`, `<cycles> release <button>` or
+/// `<cycles> check <name>`. Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    pub events: Vec<(u32, Action)>,
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = vec![];
+
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let [cycles, action, argument] = words[..] else {
+                return Err(format!("line {}: expected `<cycles> <action> <argument>`", number));
+            };
+
+            let cycles = cycles.parse().map_err(|_| format!("line {}: invalid cycle count `{}`", number, cycles))?;
+            let button = || match argument {
+                "A" | "a" => Ok(Button::A),
+                "B" | "b" => Ok(Button::B),
+                "C" | "c" => Ok(Button::C),
+                _ => Err(format!("line {}: unknown button `{}`", number, argument)),
+            };
+            let action = match action {
+                "press" => Action::Press(button()?),
+                "release" => Action::Release(button()?),
+                "check" => Action::Check(argument.to_string()),
+                _ => return Err(format!("line {}: unknown action `{}`", number, action)),
+            };
+
+            events.push((cycles, action));
+        }
+
+        events.sort_by_key(|(cycles, _)| *cycles);
+        Ok(Self { events })
+    }
+}
+
+/// A checkpoint whose LCD didn't match its golden.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub checkpoint: String,
+    pub cycles: u32,
+    pub expected: Option<String>,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    /// Expected and actual pictures side by side, with differing rows marked.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "checkpoint `{}` at cycle {}:", self.checkpoint, self.cycles)?;
+
+        let Some(expected) = &self.expected else {
+            writeln!(f, "no golden, actual:")?;
+            return write!(f, "{}", self.actual);
+        };
+
+        writeln!(f, "  {:<33}actual", "expected")?;
+        let width = self.actual.lines().map(str::len).max().unwrap_or(0);
+        let expected_lines = expected.lines().chain(std::iter::repeat(""));
+        let actual_lines = self.actual.lines().chain(std::iter::repeat(""));
+        let rows = expected.lines().count().max(self.actual.lines().count());
+
+        for (expected, actual) in expected_lines.zip(actual_lines).take(rows) {
+            let marker = if expected == actual { ' ' } else { '>' };
+            writeln!(f, "{} {:<w$} {}", marker, expected, actual, w = width.max(32))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Rom(RomError),
+    Emulation { pc: usize, error: EmulationError },
+    /// A golden couldn't be written.
+    Write { path: PathBuf, error: io::Error },
+    Mismatches(Vec<Mismatch>),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::Rom(error) => write!(f, "can't load ROM: {}", error),
+            GoldenError::Emulation { pc, error } => write!(f, "{:#06X}: {}", pc, error),
+            GoldenError::Write { path, error } => write!(f, "can't write {}: {}", path.display(), error),
+            GoldenError::Mismatches(mismatches) => mismatches.iter().try_for_each(|mismatch| write!(f, "{}", mismatch)),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// Runs a ROM headless through a script, comparing the LCD at each checkpoint with the
+/// text-art golden `<checkpoint>.txt` in `goldens`.
+///
+/// With `update`, goldens are written instead of compared.
+pub fn run(rom: Vec<u8>, profile: ChipProfile, script: &Script, goldens: &Path, update: bool) -> Result<(), GoldenError> {
+    let mut interpreter = Interpreter::load(&rom, profile).map_err(GoldenError::Rom)?;
+    interpreter.set_lcd_persistence(0);
+
+    let mut session = Session::new(interpreter);
+    let mut mismatches = vec![];
+
+    for (cycles, action) in &script.events {
+        // The session starts from reset, so its cycles are the script's
+        let remaining = u64::from(*cycles).saturating_sub(session.cycles());
+        session.wait_cycles(remaining).map_err(|error| match error {
+            SessionError::Emulation(error) => GoldenError::Emulation { pc: session.interpreter.pc(), error },
+            SessionError::Timeout { .. } => unreachable!("waiting has no timeout"),
+        })?;
+
+        let interpreter = &mut session.interpreter;
+        match action {
+            Action::Press(button) => interpreter.press_button(*button),
+            Action::Release(button) => interpreter.release_button(*button),
+            Action::Check(name) => {
+                let path = golden_path(goldens, name);
+                let actual = session.frame().to_text_art();
+
+                if update {
+                    fs::write(&path, &actual).map_err(|error| GoldenError::Write { path, error })?;
+                    continue;
+                }
+
+                let expected = fs::read_to_string(&path).ok();
+                if expected.as_deref() != Some(actual.as_str()) {
+                    mismatches.push(Mismatch {
+                        checkpoint: name.clone(),
+                        cycles: session.interpreter.state.cycles,
+                        expected,
+                        actual,
+                    });
+                }
+            },
+        }
+    }
+
+    if mismatches.is_empty() { Ok(()) } else { Err(GoldenError::Mismatches(mismatches)) }
+}
+
+fn golden_path(goldens: &Path, name: &str) -> PathBuf {
+    goldens.join(format!("{}.txt", name))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, process};
+
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    fn parse_script() {
+        let script = Script::parse("# hatch\n1000 check egg\n\n200 press A\n300 release a\n").unwrap();
+        assert_eq!(script.events, vec![
+            (200, Action::Press(Button::A)),
+            (300, Action::Release(Button::A)),
+            (1000, Action::Check("egg".to_string())),
+        ]);
+
+        assert!(Script::parse("100 press D").is_err());
+        assert!(Script::parse("soon check egg").is_err());
+    }
+
+    #[test]
+    fn run_and_compare() {
+        let profile = ChipProfile::e0c6s46;
+        // X = 0xE00, Y = K0 ; loop: LD MX,MY ; JP loop. The buttons show on the LCD.
+        let rom = fixture::rom(&profile(), &[0xE0E, 0xE80, 0xB00, 0xE0F, 0xE90, 0x840, 0xECB, 0x006]);
+        let script = Script::parse("5000 check idle\n10000 press B\n20000 check pressed\n").unwrap();
+        let goldens = env::temp_dir().join(format!("rustchi-golden-{}", process::id()));
+        fs::create_dir_all(&goldens).unwrap();
+        let golden = |name| fs::read_to_string(golden_path(&goldens, name)).unwrap();
+        let expect_mismatches = |result| match result {
+            Err(GoldenError::Mismatches(mismatches)) => mismatches,
+            result => panic!("expected mismatches, got {:?}", result),
+        };
+
+        run(rom.clone(), profile(), &script, &goldens, true).unwrap();
+        assert_ne!(golden("idle"), golden("pressed"));
+        run(rom.clone(), profile(), &script, &goldens, false).unwrap();
+
+        fs::write(golden_path(&goldens, "pressed"), golden("idle")).unwrap();
+        let mismatches = expect_mismatches(run(rom.clone(), profile(), &script, &goldens, false));
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].checkpoint, "pressed");
+        assert_eq!(mismatches[0].expected.as_deref(), Some(golden("idle").as_str()));
+        let diff = mismatches[0].to_string();
+        assert!(diff.starts_with("checkpoint `pressed` at cycle 2000"));
+        assert!(diff.lines().any(|line| line.starts_with('>')));
+
+        fs::remove_file(golden_path(&goldens, "idle")).unwrap();
+        let missing = expect_mismatches(run(rom.clone(), profile(), &script, &goldens, false));
+        assert!(missing[0].to_string().contains("no golden, actual:"));
+
+        fs::remove_dir_all(&goldens).unwrap();
+        assert!(matches!(run(rom, profile(), &script, &goldens, true), Err(GoldenError::Write { .. })));
+        assert!(matches!(run(vec![0; 5], profile(), &script, &goldens, false), Err(GoldenError::Rom(_))));
+    }
+}
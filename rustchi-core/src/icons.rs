@@ -0,0 +1,56 @@
+use bitflags::bitflags;
+
+use crate::primitive::u1;
+
+bitflags! {
+    /// Tamagotchi P1 status icons, top row first.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Icons: u8 {
+        const FOOD = 0x1 << 0;
+        const LIGHT = 0x1 << 1;
+        const GAME = 0x1 << 2;
+        const MEDICINE = 0x1 << 3;
+        const BATHROOM = 0x1 << 4;
+        const STATUS = 0x1 << 5;
+        const TRAINING = 0x1 << 6;
+        const ATTENTION = 0x1 << 7;
+    }
+}
+
+impl Icons {
+    /// Decodes the icons from the LCD dots. The top row is driven by SEG 32 on COM0-COM3,
+    /// the bottom row by SEG 36 on COM12-COM15.
+    pub fn from_dots(dots: &[[u1; 40]; 16]) -> Self {
+        Self::all().iter()
+            .filter(|icon| {
+                let (com, seg) = icon.dot();
+                dots[com][seg] == u1::ON
+            })
+            .collect()
+    }
+
+    /// COM and SEG of a single icon.
+    pub fn dot(self) -> (usize, usize) {
+        let index = self.bits().trailing_zeros() as usize;
+        if index < 4 {
+            (index, 32)
+        } else {
+            (index + 8, 36)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let mut dots = [[u1::OFF; 40]; 16];
+        dots[1][32] = u1::ON;
+        dots[15][36] = u1::ON;
+        dots[15][35] = u1::ON;
+
+        assert_eq!(Icons::from_dots(&dots), Icons::LIGHT | Icons::ATTENTION);
+    }
+}
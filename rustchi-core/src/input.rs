@@ -1,21 +1,27 @@
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    interrupt::Interrupt,
+    memory::{REG_DFK03_DFK02_DFK01_DFK00, REG_EIK03_EIK02_EIK01_EIK00, REG_EIK13_EIK12_EIK11_EIK10, REG_K03_K02_K01_K00, REG_K13_K12_K11_K10},
+    peripheral::Peripheral,
+};
 
 #[derive(Clone)]
 pub struct Input {
     pub state: u4,
+    pub k1_state: u4,
 }
 
 impl Input {
     pub fn all_high() -> Self {
-        Self {state: u4![0b1111]}
+        Self {state: u4![0b1111], k1_state: u4![0b1111]}
     }
 
     pub fn with_button_pressed(&self, button: Button) -> Self {
-        Self {state: !(!self.state | button.to_u4())}
+        Self {state: !(!self.state | button.to_u4()), ..self.clone()}
     }
 
     pub fn with_button_released(&self, button: Button) -> Self {
-        Self {state: (self.state | button.to_u4())}
+        Self {state: (self.state | button.to_u4()), ..self.clone()}
     }
 
     pub fn is_button_pressed(&self, button: Button) -> bool {
@@ -23,6 +29,7 @@ impl Input {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Button {
     A,
     B,
@@ -37,4 +44,58 @@ impl Button {
             Button::C => u4![0b0001],
         }
     }
-}
\ No newline at end of file
+}
+
+/// K0 and K1 input ports, raising their interrupts as the inputs change.
+#[derive(Clone, Default)]
+pub struct InputPorts {
+    k0_pending: bool,
+    k1_pending: bool,
+}
+
+impl InputPorts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drives the ports with new input levels.
+    pub fn set(&mut self, input: &Input, registers: &mut [u4]) {
+        let prev_k0 = std::mem::replace(&mut registers[REG_K03_K02_K01_K00], input.state);
+        let prev_k1 = std::mem::replace(&mut registers[REG_K13_K12_K11_K10], input.k1_state);
+
+        // K00-K03 interrupt when an enabled input stops matching the input comparison register
+        let comparison = registers[REG_DFK03_DFK02_DFK01_DFK00];
+        let was_matching = !(prev_k0 ^ comparison);
+        let is_matching = !(input.state ^ comparison);
+        if (was_matching & !is_matching & registers[REG_EIK03_EIK02_EIK01_EIK00]) != u4![0] {
+            self.k0_pending = true;
+        }
+
+        // K10-K13 interrupt on the falling edge of an enabled input
+        let falling = prev_k1 & !input.k1_state;
+        if (falling & registers[REG_EIK13_EIK12_EIK11_EIK10]) != u4![0] {
+            self.k1_pending = true;
+        }
+    }
+}
+
+impl Peripheral for InputPorts {
+    fn handles(&self, addr: usize) -> bool {
+        matches!(addr, REG_K03_K02_K01_K00 | REG_DFK03_DFK02_DFK01_DFK00 | REG_K13_K12_K11_K10)
+    }
+
+    fn interrupt_request(&mut self) -> Option<(Interrupt, u4)> {
+        if std::mem::take(&mut self.k0_pending) {
+            return Some((Interrupt::K0, u4![0b0001]));
+        }
+        std::mem::take(&mut self.k1_pending).then_some((Interrupt::K1, u4![0b0001]))
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
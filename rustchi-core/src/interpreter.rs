@@ -1,58 +1,161 @@
-use crate::{memory, prelude::*};
+use crate::prelude::*;
 
 use crate::{
     change::*,
+    chip::ChipProfile,
+    error::{EmulationError, ErrorPolicy, StepOutcome},
+    frame::Frame,
     immediate::Source,
     opcode::*,
     registers::*,
+    rom::{Rom, RomError},
+    savestate::{SaveStateError, StateReader, StateWriter},
     input::Button,
+    lcd::LcdController,
+    map::Diagnostic,
+    peripheral::Bus,
+    serial::{Serial, SerialLink},
+    svd::{Battery, Svd},
+    watchdog::{Watchdog, WatchdogAction},
 };
 
 use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::ops::Add;
+use std::rc::Rc;
 use std::usize;
 
-use tap::Tap;
-
 pub struct Interpreter {
     pub state: State,
     pub prev_pc: Option<usize>,
     pub changes: Changes,
-    pub rom: Vec<u8>,
+    pub rom: Rom,
     pub cycle_counter: u64,
+    last_frame: Option<Frame>,
+    policy: ErrorPolicy,
  }
 
  impl Interpreter {
-    pub fn load(bytes: Vec<u8>) -> Self {
+    /// Loads a ROM dump for the given chip, in any of the formats `Rom::load` detects.
+    pub fn load(bytes: &[u8], profile: ChipProfile) -> Result<Self, RomError> {
+        let rom = Rom::load(bytes, &profile)?;
+        Ok(Self::with_rom(rom, profile))
+    }
+
+    pub fn with_rom(rom: Rom, profile: ChipProfile) -> Self {
         Self {
-            state: State::new(),
+            state: State::with_profile(profile),
             prev_pc: Option::None,
             changes: Changes::new(),
-            rom: bytes,
+            rom,
             cycle_counter: 0,
+            last_frame: None,
+            policy: ErrorPolicy::default(),
         }
     }
 
+    /// What the LCD shows right now. `dirty` tells whether it changed since the previous call.
+    pub fn frame(&mut self) -> Frame {
+        let mut frame = Frame::from_memory(&mut self.state.memory);
+        frame.dirty = !self.last_frame.as_ref().is_some_and(|last| last.same_picture(&frame));
+        self.last_frame = Some(frame.clone());
+        frame
+    }
+
     pub fn press_button(&mut self, button: Button) {
-        self.state.input = self.state.input.with_button_pressed(button);
+        self.state.set_input(self.state.input.with_button_pressed(button));
     }
 
     pub fn release_button(&mut self, button: Button) {
-        self.state.input = self.state.input.with_button_released(button);
+        self.state.set_input(self.state.input.with_button_released(button));
+    }
+
+    pub fn connect_serial(&mut self, link: impl SerialLink + 'static) {
+        if let Some(serial) = self.state.memory.bus.get_mut::<Serial>() {
+            serial.link = Some(Rc::new(RefCell::new(link)));
+        }
+    }
+
+    pub fn configure_watchdog(&mut self, enabled: bool, action: WatchdogAction) {
+        if let Some(watchdog) = self.state.memory.bus.get_mut::<Watchdog>() {
+            watchdog.enabled = enabled;
+            watchdog.action = action;
+        }
+    }
+
+    pub fn battery(&self) -> Battery {
+        self.state.memory.bus.get::<Svd>().map(|svd| svd.battery).unwrap_or_default()
+    }
+
+    pub fn set_battery(&mut self, battery: Battery) {
+        if let Some(svd) = self.state.memory.bus.get_mut::<Svd>() {
+            svd.battery = battery;
+        }
+    }
+
+    /// The peripherals on the I/O registers, to configure them or swap in other implementations.
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.state.memory.bus
+    }
+
+    /// Share of the previous frame kept in the next one, out of 256. 0 shows each frame's average alone.
+    pub fn set_lcd_persistence(&mut self, persistence: u8) {
+        if let Some(lcd) = self.state.memory.bus.get_mut::<LcdController>() {
+            lcd.persistence.persistence = persistence;
+        }
+    }
+
+    /// Called with every access the hardware would ignore, such as writes outside of RAM.
+    pub fn set_diagnostic_hook(&mut self, hook: impl Fn(Diagnostic) + 'static) {
+        self.state.memory.diagnostics = Some(Rc::new(hook));
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.policy = policy;
     }
 
     pub fn reset_cycle_counter(&mut self) {
         self.cycle_counter = 0;
     }
 
+    /// Everything needed to resume emulation later, except the configuration like the battery,
+    /// the serial link or the watchdog.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::with_header(self.profile().name);
+        self.state.save_state(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Restores a save state. On error, the emulation carries on untouched.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::with_header(bytes, self.profile().name)?;
+        let mut state = self.state.clone();
+        state.load_state(&mut reader)?;
+
+        self.state = state;
+        self.prev_pc = None;
+        self.changes = Changes::new();
+        self.last_frame = None;
+        Ok(())
+    }
+
+    /// Initial reset, like the watchdog or pulling the battery tab does.
+    pub fn reset(&mut self) {
+        self.state.reset();
+        self.prev_pc = None;
+        self.changes = Changes::new();
+    }
+
+    pub fn profile(&self) -> &ChipProfile {
+        &self.state.memory.profile
+    }
+
     pub fn pc(&self) -> usize {
         self.state.pc()
     }
 
     pub fn words(&self) -> impl Iterator<Item = u16> + '_ {
-        self.rom.chunks_exact(2).map(|bytes|
-            u16::from_be_bytes([bytes[0], bytes[1]])
-        )
+        self.rom.words.iter().copied()
     }
 
     pub fn disassemble(&self, offset: usize) -> impl Iterator<Item = (usize, String)> + '_ {
@@ -66,11 +169,43 @@ pub struct Interpreter {
         Opcode::decode(self.words().skip(self.pc()).take(1).last().unwrap())
     }
 
-    pub fn step(&mut self) {
-        let opcode = self.next_opcode();
+    pub fn step(&mut self) -> Result<StepOutcome, EmulationError> {
+        if self.state.halted {
+            // The CPU is stopped, but the oscillator keeps the timers running until an interrupt wakes it up.
+            self.changes = Changes::new();
+            self.run_cycles(Opcode::HALT.cycles(), true);
+            return self.check_memory().map(|_| StepOutcome::Halted);
+        }
+
+        let mut opcode = self.next_opcode();
+        if let Opcode::RETS | Opcode::TODO(_) | Opcode::UNKNOWN = opcode {
+            let pc = self.pc();
+            let word = self.words().nth(pc).unwrap_or_default();
+            self.recover(EmulationError::UnimplementedOpcode { pc, word })?;
+            opcode = Opcode::NOP5;
+        }
+
         self.prev_pc = Option::Some(self.pc());
 
         self.exec(opcode);
+        self.check_memory().map(|_| StepOutcome::Executed)
+    }
+
+    fn check_memory(&self) -> Result<(), EmulationError> {
+        match self.state.memory.take_error() {
+            Some(error) => self.recover(error),
+            None => Ok(()),
+        }
+    }
+
+    fn recover(&self, error: EmulationError) -> Result<(), EmulationError> {
+        match self.policy {
+            ErrorPolicy::Strict => Err(error),
+            ErrorPolicy::Lenient => {
+                log::warn!("{}, ignoring it", error);
+                Ok(())
+            },
+        }
     }
 
     fn read_source(&self, source: Source) -> u8 {
@@ -96,10 +231,6 @@ pub struct Interpreter {
         let memory = &self.state.memory;
         let mut changes = Changes::new();
 
-        if memory.get(memory::REG_K03_K02_K01_K00) != self.state.input.state {
-            changes.memory(Memory { address: u12![memory::REG_K03_K02_K01_K00], value: self.state.input.state });
-        }
-
         match opcode.borrow() {
             Opcode::LD(reg, i) => {
                 let data = self.read_source(*i);
@@ -184,33 +315,43 @@ pub struct Interpreter {
                 op.exec(&mut self.state);
                 changes.append(&mut self.state.changes)
             }
-            Opcode::RETS => todo!("{}", opcode),
-            Opcode::HALT => todo!("{}", opcode),
-            Opcode::TODO(_) => todo!("{}", opcode),
-            Opcode::UNKNOWN => todo!("{}", opcode),
+            Opcode::RETS => unreachable!("{} is rejected by step()", opcode),
+            Opcode::HALT => {
+                self.state.halted = true;
+                &mut changes
+            }
+            Opcode::TODO(_) | Opcode::UNKNOWN => unreachable!("{} is rejected by step()", opcode),
         };
 
-        self.state.apply(&changes).tap_mut(|state| {
-            let delta_cycles = opcode.cycles();
-            state.cycles += delta_cycles;
-            state.update_timers(delta_cycles);
+        self.state.apply(&changes);
+        self.changes = changes;
+
+        let interruptible = match &opcode {
+            Opcode::Op(op) => op.interruptible(),
+            _ => true,
+        };
 
-            let process_interrupts = match opcode {
-                Opcode::Op(op) => op.interruptible(),
-                _ => true,
-            };
+        self.run_cycles(opcode.cycles(), interruptible);
+    }
 
-            if process_interrupts {
-                if let Some(interrupt_pcs) = state.check_interrupts() {
-                    let int_cycles = state.process_interrupts(interrupt_pcs);
-                    self.cycle_counter += int_cycles;
-                }
-                state.registers.NPP = state.registers.PCP;
-            };
+    fn run_cycles(&mut self, delta_cycles: u32, interruptible: bool) {
+        let state = &mut self.state;
+        let osc1_cycles = state.osc1_cycles(delta_cycles);
+        state.cycles = state.cycles.wrapping_add(osc1_cycles);
+        state.update_timers(osc1_cycles);
 
-            self.cycle_counter += u64::from(delta_cycles);
-        });
+        if interruptible {
+            if let Some(interrupt) = state.check_interrupts() {
+                let int_cycles = state.take_interrupt(interrupt);
+                let osc1_cycles = state.osc1_cycles(int_cycles);
+                state.cycles = state.cycles.wrapping_add(osc1_cycles);
+                state.update_timers(osc1_cycles);
+                self.cycle_counter += u64::from(int_cycles);
+            }
+            state.registers.NPP = state.registers.PCP;
+        };
 
-        self.changes = changes;
+        state.update_clock();
+        self.cycle_counter += u64::from(delta_cycles);
     }
 }
@@ -0,0 +1,210 @@
+use std::cell::Cell;
+
+use crate::{memory, prelude::*, peripheral::Peripheral, savestate::{SaveStateError, StateReader, StateWriter}};
+
+pub const INTERRUPT_CYCLES: u32 = 12;
+
+/// Interrupt sources, with their vectors (PCS on page 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Watchdog,
+    ClockTimer,
+    Stopwatch,
+    K0,
+    K1,
+    Serial,
+    ProgTimer,
+}
+
+impl Interrupt {
+    /// Maskable interrupts, from high to low priority.
+    pub const MASKABLE: [Interrupt; 6] = [
+        Interrupt::ProgTimer,
+        Interrupt::Serial,
+        Interrupt::K1,
+        Interrupt::K0,
+        Interrupt::Stopwatch,
+        Interrupt::ClockTimer,
+    ];
+
+    pub fn vector(self) -> u8 {
+        match self {
+            Interrupt::Watchdog => 0x00,
+            Interrupt::ClockTimer => 0x02,
+            Interrupt::Stopwatch => 0x04,
+            Interrupt::K0 => 0x06,
+            Interrupt::K1 => 0x08,
+            Interrupt::Serial => 0x0A,
+            Interrupt::ProgTimer => 0x0C,
+        }
+    }
+
+    fn mask_register(self) -> usize {
+        match self {
+            Interrupt::Watchdog => unreachable!("NMI can't be masked"),
+            Interrupt::ClockTimer => memory::REG_EIT1_EIT2_EIT8_EIT32,
+            Interrupt::Stopwatch => memory::REG_EISW1_EISW0,
+            Interrupt::K0 => memory::REG_EIK03_EIK02_EIK01_EIK00,
+            Interrupt::K1 => memory::REG_EIK13_EIK12_EIK11_EIK10,
+            Interrupt::Serial => memory::REG_EISIO,
+            Interrupt::ProgTimer => memory::REG_EIPT,
+        }
+    }
+
+    // The NMI has no factor flags
+    fn factor_index(self) -> Option<usize> {
+        usize::from(self.vector() / 2).checked_sub(1)
+    }
+
+    fn is_enabled(self, factor: u4, mask: u4) -> bool {
+        match self {
+            // The input masks select which pins raise the factor, so any enabled pin lets it through.
+            Interrupt::K0 | Interrupt::K1 => factor != u4![0] && mask != u4![0],
+            _ => (factor & mask) != u4![0],
+        }
+    }
+}
+
+/// Interrupt factor flags raised by the peripherals, and the NMI line.
+///
+/// Factor flags are set regardless of the mask registers and are cleared when the ROM reads them.
+#[derive(Clone)]
+pub struct InterruptController {
+    factors: [Cell<u4>; 6],
+    nmi: bool,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self {
+            factors: std::array::from_fn(|_| Cell::new(u4::MIN)),
+            nmi: false,
+        }
+    }
+
+    /// Sets factor flags. The NMI has none, see `raise_nmi`.
+    pub fn raise(&self, interrupt: Interrupt, factor: u4) {
+        if let Some(flags) = self.factor(interrupt) {
+            flags.set(flags.get() | factor);
+        }
+    }
+
+    pub fn raise_nmi(&mut self) {
+        self.nmi = true;
+    }
+
+    /// Reads the factor flags without clearing them, for debuggers.
+    pub fn peek_factor(&self, interrupt: Interrupt) -> u4 {
+        self.factor(interrupt).map_or(u4![0], Cell::get)
+    }
+
+    /// Reads the factor flags the way the ROM does, resetting them.
+    pub fn read_factor(&self, interrupt: Interrupt) -> u4 {
+        self.factor(interrupt).map_or(u4![0], |flags| flags.replace(u4![0]))
+    }
+
+    fn factor(&self, interrupt: Interrupt) -> Option<&Cell<u4>> {
+        interrupt.factor_index().map(|index| &self.factors[index])
+    }
+
+    /// The interrupt the CPU should take next, if any.
+    pub fn pending(&self, enabled: bool, bytes: &[u4]) -> Option<Interrupt> {
+        if self.nmi {
+            return Some(Interrupt::Watchdog);
+        }
+
+        if !enabled {
+            return None;
+        }
+
+        Interrupt::MASKABLE.into_iter().find(|interrupt|
+            interrupt.is_enabled(self.peek_factor(*interrupt), bytes[interrupt.mask_register()])
+        )
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        self.factors.iter().for_each(|factor| writer.u4(factor.get()));
+        writer.bool(self.nmi);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for factor in &self.factors {
+            factor.set(reader.u4("interrupt factor")?);
+        }
+        self.nmi = reader.bool("NMI")?;
+        Ok(())
+    }
+
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        if interrupt == Interrupt::Watchdog {
+            self.nmi = false;
+        }
+    }
+}
+
+impl Default for InterruptController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Interrupt mask registers. They keep what the ROM wrote, which `InterruptController::pending` reads back.
+#[derive(Clone, Default)]
+pub struct InterruptMasks {}
+
+impl InterruptMasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Peripheral for InterruptMasks {
+    fn handles(&self, addr: usize) -> bool {
+        (memory::REG_EIT1_EIT2_EIT8_EIT32..=memory::REG_EIK13_EIK12_EIK11_EIK10).contains(&addr)
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::State;
+
+    #[test]
+    fn priority_and_stack_layout() {
+        let mut state = State::new();
+        state.memory.set(memory::REG_EIT1_EIT2_EIT8_EIT32, u4![0b1000]);
+        state.memory.set(memory::REG_EIPT, u4![0b0001]);
+        state.memory.interrupts.raise(Interrupt::ClockTimer, u4![0b1000]);
+        state.memory.interrupts.raise(Interrupt::ProgTimer, u4![0b0001]);
+        assert_eq!(state.check_interrupts(), None);
+
+        state.flags.set(Flags::I, true);
+        state.registers.SP = 0x40;
+        state.registers.PCP = u4![0x3];
+        state.registers.PCS = 0x5A;
+
+        assert_eq!(state.check_interrupts(), Some(Interrupt::ProgTimer));
+        assert_eq!(state.take_interrupt(Interrupt::ProgTimer), INTERRUPT_CYCLES);
+        assert_eq!(state.pc(), 0x10C);
+        assert_eq!(state.registers.SP, 0x3D);
+        assert_eq!(state.memory.slice(0x3D..0x40), vec![u4![0xA], u4![0x5], u4![0x3]]);
+        assert!(!state.flags.contains(Flags::I));
+
+        state.flags.set(Flags::I, true);
+        assert_eq!(state.memory.get(memory::REG_PROGRAMMABLE_TIMER_INTERRUPT_FACTOR_FLAGS), u4![0b0001]);
+        assert_eq!(state.check_interrupts(), Some(Interrupt::ClockTimer));
+    }
+
+    #[test]
+    fn nmi_has_no_factor() {
+        let interrupts = InterruptController::new();
+        interrupts.raise(Interrupt::Watchdog, u4![0b0001]);
+        assert_eq!(interrupts.peek_factor(Interrupt::Watchdog), u4![0]);
+        assert_eq!(interrupts.read_factor(Interrupt::Watchdog), u4![0]);
+        assert!(Interrupt::MASKABLE.iter().all(|interrupt| interrupts.peek_factor(*interrupt) == u4![0]));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{
+    prelude::*,
+    chip::ChipProfile,
+    error::EmulationError,
+    icons::Icons,
+    map::Region,
+    memory::{REG_ALOFF_ALON_LDUTY_HLMOD, REG_LC3_LC2_LC1_LC0},
+    peripheral::Peripheral,
+    persistence::LcdPersistence,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+pub const LCD_CONTRAST_DEFAULT: u8 = 0x8;
+
+/// LCD driver: the segments the display RAM drives, the all on/off and duty controls, the contrast,
+/// and the persistence of the panel.
+#[derive(Clone)]
+pub struct LcdController {
+    // Where the display RAM is and which segments it drives
+    profile: ChipProfile,
+    segments: [[u1; 40]; 16],
+    control: u4,
+    contrast: u4,
+    pub persistence: LcdPersistence,
+    // The picture changed since `persistence` last saw it
+    dirty: bool,
+}
+
+impl LcdController {
+    pub fn new(profile: &ChipProfile) -> Self {
+        Self {
+            profile: profile.clone(),
+            segments: [[u1::OFF; 40]; 16],
+            control: u4![0],
+            contrast: u4![LCD_CONTRAST_DEFAULT],
+            persistence: LcdPersistence::new(),
+            dirty: false,
+        }
+    }
+
+    fn set_segments(&mut self, addr: usize, val: u4) {
+        let Some((base_com, seg)) = self.profile.display_position(addr) else {
+            return;
+        };
+
+        for i in 0..4 {
+            let val = (val >> u4![i]) & u4![1];
+            self.segments[base_com + i][seg] = u1![val];
+        }
+    }
+
+    /// Dots as the panel shows them, after the all on/off controls and the drive duty.
+    pub fn dots(&self) -> [[u1; 40]; 16] {
+        // 1/8 duty only drives COM0-COM7
+        let driven_coms = if self.control.is_set(u4![0b0010]) { 8 } else { 16 };
+        let mut dots = self.segments;
+
+        for (com, row) in dots.iter_mut().enumerate() {
+            for dot in row.iter_mut() {
+                if self.control.is_set(u4![0b1000]) || com >= driven_coms {
+                    *dot = u1::OFF;
+                } else if self.control.is_set(u4![0b0100]) {
+                    *dot = u1::ON;
+                }
+            }
+        }
+
+        dots
+    }
+
+    /// Darkness of each dot from 0 to 255. Low contrast fades the lit dots, high contrast
+    /// starts to show the unlit ones.
+    pub fn intensity(&self) -> [[u8; 40]; 16] {
+        let contrast = u8::from(self.contrast);
+        let on = (255 * u16::from(contrast + 1) / u16::from(LCD_CONTRAST_DEFAULT + 1)).min(255) as u8;
+        let off = contrast.saturating_sub(LCD_CONTRAST_DEFAULT) * 16;
+
+        self.dots().map(|row| row.map(|dot| if dot == u1::ON { on } else { off }))
+    }
+
+    pub fn icons(&self) -> Icons {
+        Icons::from_dots(&self.dots())
+    }
+
+    /// Ends the frame, see `LcdPersistence::take_frame`.
+    pub fn take_frame(&mut self) -> [[u8; 40]; 16] {
+        self.refresh();
+        self.persistence.take_frame()
+    }
+
+    // Rebuilds the intensity grid once for all the writes since the last tick
+    fn refresh(&mut self) {
+        if std::mem::take(&mut self.dirty) {
+            let intensity = self.intensity();
+            self.persistence.update(intensity);
+        }
+    }
+
+    // A blank panel, keeping the persistence setting
+    fn clear_persistence(&mut self) {
+        let persistence = self.persistence.persistence;
+        self.persistence = LcdPersistence::new();
+        self.persistence.persistence = persistence;
+    }
+}
+
+impl Peripheral for LcdController {
+    fn handles(&self, addr: usize) -> bool {
+        matches!(addr, REG_ALOFF_ALON_LDUTY_HLMOD | REG_LC3_LC2_LC1_LC0) || self.profile.region(addr) == Region::Display
+    }
+
+    fn write(&mut self, addr: usize, value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        match addr {
+            REG_ALOFF_ALON_LDUTY_HLMOD => self.control = value,
+            REG_LC3_LC2_LC1_LC0 => self.contrast = value,
+            _ => self.set_segments(addr, value),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn tick(&mut self, delta_cycles: u32, _registers: &mut [u4]) {
+        self.refresh();
+        self.persistence.tick(delta_cycles);
+    }
+
+    fn reset(&mut self) {
+        self.segments = [[u1::OFF; 40]; 16];
+        self.control = u4![0];
+        self.contrast = u4![LCD_CONTRAST_DEFAULT];
+        self.clear_persistence();
+        self.dirty = false;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u4(self.control);
+        writer.u4(self.contrast);
+        self.segments.iter().flatten().for_each(|segment| writer.u1(*segment));
+    }
+
+    // The panel shows the restored picture with no trace of the previous one
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.control = reader.u4("LCD control")?;
+        self.contrast = reader.u4("LCD contrast")?;
+        for segment in self.segments.iter_mut().flatten() {
+            *segment = reader.u1("LCD segment")?;
+        }
+        self.clear_persistence();
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::Memory;
+
+    fn lcd(memory: &mut Memory) -> &mut LcdController {
+        memory.bus.get_mut::<LcdController>().unwrap()
+    }
+
+    #[test]
+    fn controls() {
+        let mut memory = Memory::new();
+        memory.set(0xE00, u4![0b0001]);
+        memory.set(0xE80, u4![0b0001]);
+        assert_eq!(lcd(&mut memory).dots()[0][0], u1::ON);
+        assert_eq!(lcd(&mut memory).dots()[8][0], u1::ON);
+        assert_eq!(lcd(&mut memory).intensity()[0][..2], [255, 0]);
+
+        memory.set(REG_ALOFF_ALON_LDUTY_HLMOD, u4![0b0010]);
+        assert_eq!(lcd(&mut memory).dots()[8][0], u1::OFF);
+
+        memory.set(REG_ALOFF_ALON_LDUTY_HLMOD, u4![0b0100]);
+        assert!(lcd(&mut memory).dots().iter().flatten().all(|dot| *dot == u1::ON));
+
+        memory.set(REG_ALOFF_ALON_LDUTY_HLMOD, u4![0b1100]);
+        assert!(lcd(&mut memory).dots().iter().flatten().all(|dot| *dot == u1::OFF));
+
+        memory.set(REG_ALOFF_ALON_LDUTY_HLMOD, u4![0b0000]);
+        memory.set(REG_LC3_LC2_LC1_LC0, u4![0x2]);
+        assert_eq!(lcd(&mut memory).intensity()[0][..2], [85, 0]);
+        memory.set(REG_LC3_LC2_LC1_LC0, u4![0xF]);
+        assert_eq!(lcd(&mut memory).intensity()[0][..2], [255, 112]);
+    }
+
+    #[test]
+    fn frame() {
+        let mut memory = Memory::new();
+        lcd(&mut memory).persistence.persistence = 0;
+
+        memory.set(0xE00, u4![0b0001]);
+        memory.tick(10);
+        memory.set(0xE00, u4![0b0000]);
+        memory.set(0xE80, u4![0b0001]);
+        memory.tick(30);
+        let frame = lcd(&mut memory).take_frame();
+        assert_eq!((frame[0][0], frame[8][0]), (63, 191));
+
+        memory.set(0xE80, u4![0b0000]);
+        memory.tick(10);
+        assert_eq!(lcd(&mut memory).take_frame()[8][0], 0);
+    }
+}
@@ -0,0 +1,28 @@
+use std::ops::Range;
+
+use crate::primitive::u4;
+
+/// I/O registers, at the same place on every chip of the family. RAM and display RAM depend on
+/// the chip, see `ChipProfile`.
+pub const IO: Range<usize> = 0xF00..0xF80;
+
+/// What the data bus reads outside of the mapped regions.
+pub const UNMAPPED_VALUE: u4 = u4::new(0);
+
+/// Regions of the data memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ram,
+    Display,
+    Io,
+    Unmapped,
+}
+
+/// Accesses that the hardware ignores, usually a sign of a bug in the ROM or the CPU emulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    UnmappedRead { addr: usize },
+    UnmappedWrite { addr: usize, value: u4 },
+    /// Write to a register with no writable bits.
+    ReadOnlyWrite { addr: usize, value: u4 },
+}
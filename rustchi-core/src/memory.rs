@@ -1,171 +1,247 @@
-use std::{ops::Range, cell::RefCell};
+use std::{ops::Range, cell::RefCell, rc::Rc};
 
-use crate::prelude::*;
-
-const DISP_SIZE: usize = 80;
-const ADDR_DISP1: Range<usize> = 0xE00..(0xE00 + DISP_SIZE);
-const ADDR_DISP2: Range<usize> = 0xE80..(0xE80 + DISP_SIZE);
-const DISP_SEG_ORDER: [usize; 40] = [0, 1, 2, 3, 4, 5, 6, 7, 32, 8, 9, 10, 11, 12 ,13 ,14, 15, 33, 34, 35, 31, 30, 29, 28, 27, 26, 25, 24, 36, 23, 22, 21, 20, 19, 18, 17, 16, 37, 38, 39];
+use crate::{prelude::*, chip::ChipProfile, error::EmulationError, icons::Icons, input::{Input, InputPorts}, interrupt::{Interrupt, InterruptController}, lcd::{LcdController, LCD_CONTRAST_DEFAULT}, map::{Diagnostic, Region, UNMAPPED_VALUE}, peripheral::Bus, savestate::{SaveStateError, StateReader, StateWriter}};
 
 #[derive(Clone)]
 pub struct Memory {
+    pub profile: ChipProfile,
     pub bytes: RefCell<[u4; 4096]>,
-    pub clock_timer_ticks: u32,
-    pub prog_timer_ticks: u32,
-    pub lcd: RefCell<[[u1; 40]; 16]>,
+    pub bus: Bus,
+    pub interrupts: InterruptController,
+    pub diagnostics: Option<Rc<dyn Fn(Diagnostic)>>,
+    error: RefCell<Option<EmulationError>>,
 }
 
 impl Memory {
     pub fn new() -> Self {
+        Self::with_profile(ChipProfile::e0c6s46())
+    }
+
+    pub fn with_profile(profile: ChipProfile) -> Self {
         let mut bytes = [u4::MIN; 4096];
-        bytes[REG_K03_K02_K01_K00] = u4![0b0111];
+        bytes[REG_K03_K02_K01_K00] = u4![0b1111];
+        bytes[REG_DFK03_DFK02_DFK01_DFK00] = u4![0b1111];
+        bytes[REG_K13_K12_K11_K10] = u4![0b1111];
+        bytes[REG_LC3_LC2_LC1_LC0] = u4![LCD_CONTRAST_DEFAULT];
+
+        let bus = (profile.peripherals)(&profile);
 
         Self {
+            profile,
             bytes: RefCell::new(bytes),
-            clock_timer_ticks: 0,
-            prog_timer_ticks: 0,
-            lcd: RefCell::new([[u1![0u8]; 40]; 16]),
+            bus,
+            interrupts: InterruptController::new(),
+            diagnostics: None,
+            error: RefCell::new(None),
         }
     }
 
+    /// Data memory as the ROM reads it, without the side effects: factor flags aren't cleared and
+    /// no access is diagnosed or reported.
     pub fn slice<'a>(&'a self, slice: Range<usize>) -> Vec<u4> {
-        self.bytes.borrow()[slice].into()
+        slice.map(|addr| self.peek(addr)).collect()
     }
 
-    pub fn get(&self, addr: usize) -> u4 {
-        if addr >= 0xF00 {
-            return self.get_io(addr)
+    fn peek(&self, addr: usize) -> u4 {
+        let bytes = self.bytes.borrow();
+        match self.profile.region(addr) {
+            Region::Io => {
+                let val = match factor_interrupt(addr) {
+                    Some(interrupt) => self.interrupts.peek_factor(interrupt),
+                    None => self.bus.read(addr, &bytes[..]).and_then(Result::ok).unwrap_or(bytes[addr]),
+                };
+                val & !write_only_bits(addr)
+            },
+            Region::Unmapped => UNMAPPED_VALUE,
+            _ => bytes[addr],
         }
+    }
 
-        self.bytes.borrow()[addr]
+    pub fn get(&self, addr: usize) -> u4 {
+        match self.profile.region(addr) {
+            Region::Io => {
+                let val = self.get_io(addr).unwrap_or_else(|error| {
+                    self.report(error);
+                    self.bytes.borrow()[addr]
+                });
+                val & !write_only_bits(addr)
+            },
+            Region::Unmapped => {
+                self.diagnose(Diagnostic::UnmappedRead { addr });
+                UNMAPPED_VALUE
+            },
+            _ => self.bytes.borrow()[addr],
+        }
     }
 
     pub fn set(&mut self, addr: usize, val: u4) {
-        self.bytes.borrow_mut()[addr] = val;
+        let region = self.profile.region(addr);
+        match region {
+            Region::Unmapped => return self.diagnose(Diagnostic::UnmappedWrite { addr, value: val }),
+            Region::Io if read_only_bits(addr) == u4![0b1111] => return self.diagnose(Diagnostic::ReadOnlyWrite { addr, value: val }),
+            _ => (),
+        }
 
-        if ADDR_DISP1.contains(&addr) || ADDR_DISP2.contains(&addr) {
-            self.set_lcd(addr, val);
+        // Read-only bits of mixed registers keep their value
+        let read_only = if region == Region::Io { read_only_bits(addr) } else { u4![0] };
+        let val = (self.bytes.borrow()[addr] & read_only) | (val & !read_only);
+        let previous = std::mem::replace(&mut self.bytes.borrow_mut()[addr], val);
+
+        if region == Region::Display {
+            self.bus.write(addr, val, &mut self.bytes.borrow_mut()[..], &mut self.interrupts);
         }
 
-        if addr >= 0xF00 {
-            self.set_io(addr, val)
+        if region == Region::Io {
+            if let Err(error) = self.set_io(addr, val) {
+                // Writes the emulator doesn't understand are ignored
+                self.bytes.borrow_mut()[addr] = previous;
+                self.report(error);
+            }
         };
     }
 
-    fn set_lcd(&mut self, addr: usize, val: u4) {
-        let seg = DISP_SEG_ORDER[(addr & 0x7F) >> 1];
-        let base_com = ((addr & 0x80) >> 4) + ((addr % 2) << 2);
+    /// Advances the peripherals by a number of OSC1 cycles.
+    pub fn tick(&mut self, delta_cycles: u32) {
+        self.bus.tick(delta_cycles, &mut self.bytes.borrow_mut()[..], &mut self.interrupts);
+    }
 
-        let mut lcd = self.lcd.borrow_mut();
+    /// Ends the LCD frame, blank without an LCD controller on the bus.
+    pub fn take_lcd_frame(&mut self) -> [[u8; 40]; 16] {
+        self.bus.get_mut::<LcdController>().map_or([[0; 40]; 16], LcdController::take_frame)
+    }
 
-        for i in 0..4 {
-            let com = base_com + i;
-            let val = (val >> u4![i]) & u4![1];
-            // println!("LCD {:#05X} {} {}", addr, com, seg);
-            lcd[com][seg] = u1![val];
+    pub fn set_input(&mut self, input: &Input) {
+        if let Some(ports) = self.bus.get_mut::<InputPorts>() {
+            ports.set(input, &mut self.bytes.borrow_mut()[..]);
         }
+        self.bus.poll(&mut self.interrupts);
     }
 
-    fn get_io(&self, addr: usize) -> u4 {
-        let val = {
-            self.bytes.borrow()[addr]
-        };
-        match addr {
-            REG_CLOCK_INTERRUPT_FACTOR_FLAGS |
-            REG_STOPWATCH_INTERRUPT_FACTOR_FLAGS |
-            REG_PROGRAMMABLE_TIMER_INTERRUPT_FACTOR_FLAGS |
-            REG_SERIAL_INTERRUPT_FACTOR_FLAGS |
-            REG_K00_K03_INTERRUPT_FACTOR_FLAGS |
-            REG_K10_K13_INTERRUPT_FACTOR_FLAGS => {
-                self.bytes.borrow_mut()[addr] = u4![0];
-                val
-            }
-            REG_EIT1_EIT2_EIT8_EIT32 => val,
-            REG_EISW1_EISW0 => val,
-            REG_EIPT => val,
-            REG_EISIO => val,
-            REG_EIK03_EIK02_EIK01_EIK00 => val,
-            REG_EIK13_EIK12_EIK11_EIK10 => val,
-            REG_PROG_TIMER_DATA_LO => val,
-            REG_PROG_TIMER_DATA_HI => val,
-            REG_PROG_TIMER_RELOAD_DATA_LO => val,
-            REG_PROG_TIMER_RELOAD_DATA_HI => val,
-            REG_K03_K02_K01_K00 => val,
-            REG_R43_R42_R41_R40 => val,
-            REG_CLKCHG_OSCC_VSC1_VSC0 => val,
-            REG_ALOFF_ALON_LDUTY_HLMOD => val,
-            REG_LC3_LC2_LC1_LC0 => val,
-            REG_SVDDT_SVDON_SVC1_SVC0 => val & !u4![0b1000],
-            REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0 => val,
-            REG_BZSHOT_ENVRST_ENVRT_ENVON => val,
-            REG_PTCOUT_PTC2_PTC1_PTC0 => val,
-            REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET => val,
-            _ => panic!("read IO! {:#X}", addr),
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        let bytes: Vec<u8> = self.bytes.borrow().iter().map(|nibble| u8::from(*nibble)).collect();
+        writer.bytes(&bytes);
+        self.interrupts.save_state(writer);
+        self.bus.save_state(writer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let saved = reader.bytes()?;
+        if saved.len() != self.bytes.borrow().len() {
+            return Err(SaveStateError::Corrupt("memory size"));
+        }
+        for (nibble, saved) in self.bytes.borrow_mut().iter_mut().zip(saved) {
+            *nibble = u4::try_from(*saved).map_err(|_| SaveStateError::Corrupt("memory"))?;
         }
+        self.interrupts.load_state(reader)?;
+        self.bus.load_state(reader)
     }
 
-    fn set_io(&mut self, addr: usize, val: u4) {
-        let mut bytes = self.bytes.borrow_mut();
-        match addr {
-            REG_EIT1_EIT2_EIT8_EIT32 => assert!(val == u4![0x8], "1Hz interrupt timer expected"),
-            REG_EISW1_EISW0 => assert!(val == u4![0x0], "stopwatch interrupt not expected"),
-            REG_EIPT => assert!(val == u4![0x1], "programmable timer interrupt expected"),
-            REG_EISIO => assert!(val == u4![0x0], "serial interface interrupt not expected"),
-            REG_EIK03_EIK02_EIK01_EIK00 => assert!(val == u4![0x0], "K03-K00 interrupt not expected"),
-            REG_EIK13_EIK12_EIK11_EIK10 => assert!(val == u4![0x0], "K13-K10 interrupt not expected"),
-            REG_PROG_TIMER_RELOAD_DATA_LO => (),
-            REG_PROG_TIMER_RELOAD_DATA_HI => (),
-            REG_K03_K02_K01_K00 => (),
-            REG_R43_R42_R41_R40 => (), // TODO: buzzer
-            REG_CLKCHG_OSCC_VSC1_VSC0 => (),
-            REG_ALOFF_ALON_LDUTY_HLMOD => (), // TODO: display,
-            REG_LC3_LC2_LC1_LC0 => assert!(val == u4![0x8]),
-            REG_SVDDT_SVDON_SVC1_SVC0 => (),
-            REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0 => (), // TODO: buzzer
-            REG_BZSHOT_ENVRST_ENVRT_ENVON => (), // TODO: buzzer
-            REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET => {
-                if val.is_set(u4![0b0010]) {
-                    self.clock_timer_ticks = 0;
-                }
-            }
-            REG_SWRST_SWRUN => (), // TODO: timer
-            REG_PROG_TIMER_RESET_ENABLE => {
-                if val.is_set(u4![0b0010]) {
-                    bytes[self::REG_PROG_TIMER_DATA_LO] = bytes[self::REG_PROG_TIMER_RELOAD_DATA_LO];
-                    bytes[self::REG_PROG_TIMER_DATA_HI] = bytes[self::REG_PROG_TIMER_RELOAD_DATA_HI];
-                    self.prog_timer_ticks = 0;
-                }
-            }
-            REG_PTCOUT_PTC2_PTC1_PTC0 => assert!(val == u4![0x2]), // TODO: timer
-            _ => panic!("write IO! {:#X} {:#X}", addr, val),
+    pub fn icons(&self) -> Icons {
+        self.bus.get::<LcdController>().map_or(Icons::empty(), LcdController::icons)
+    }
+
+    fn diagnose(&self, diagnostic: Diagnostic) {
+        if let Some(diagnostics) = &self.diagnostics {
+            diagnostics(diagnostic);
+        }
+    }
+
+    // Keeps the first error of the instruction, for the interpreter to pick up.
+    fn report(&self, error: EmulationError) {
+        self.error.borrow_mut().get_or_insert(error);
+    }
+
+    /// The first unexpected access since the last call.
+    pub fn take_error(&self) -> Option<EmulationError> {
+        self.error.borrow_mut().take()
+    }
+
+    fn get_io(&self, addr: usize) -> Result<u4, EmulationError> {
+        if let Some(interrupt) = factor_interrupt(addr) {
+            return Ok(self.interrupts.read_factor(interrupt));
         }
+        self.bus.read(addr, &self.bytes.borrow()[..]).unwrap_or(Err(EmulationError::UnknownIoRead { addr }))
+    }
+
+    fn set_io(&mut self, addr: usize, val: u4) -> Result<(), EmulationError> {
+        self.bus.write(addr, val, &mut self.bytes.borrow_mut()[..], &mut self.interrupts)
+            .unwrap_or(Err(EmulationError::UnknownIoWrite { addr, value: val }))
+    }
+}
+
+// Interrupt whose factor flags are read at this address.
+fn factor_interrupt(addr: usize) -> Option<Interrupt> {
+    Some(match addr {
+        REG_CLOCK_INTERRUPT_FACTOR_FLAGS => Interrupt::ClockTimer,
+        REG_STOPWATCH_INTERRUPT_FACTOR_FLAGS => Interrupt::Stopwatch,
+        REG_PROGRAMMABLE_TIMER_INTERRUPT_FACTOR_FLAGS => Interrupt::ProgTimer,
+        REG_SERIAL_INTERRUPT_FACTOR_FLAGS => Interrupt::Serial,
+        REG_K00_K03_INTERRUPT_FACTOR_FLAGS => Interrupt::K0,
+        REG_K10_K13_INTERRUPT_FACTOR_FLAGS => Interrupt::K1,
+        _ => return None,
+    })
+}
+
+// Bits the ROM can't change. Writes to registers made only of them are ignored, the other
+// registers keep these bits.
+fn read_only_bits(addr: usize) -> u4 {
+    match addr {
+        REG_CLOCK_INTERRUPT_FACTOR_FLAGS..=REG_K10_K13_INTERRUPT_FACTOR_FLAGS => u4![0b1111],
+        REG_TM3_TM2_TM1_TM0 | REG_TM7_TM6_TM5_TM4 => u4![0b1111],
+        REG_SWL3_SWL2_SWL1_SWL0 | REG_SWH3_SWH2_SWH1_SWH0 => u4![0b1111],
+        REG_K03_K02_K01_K00 | REG_K13_K12_K11_K10 => u4![0b1111],
+        REG_SVDDT_SVDON_SVC1_SVC0 => u4![0b1000],
+        _ => u4![0],
+    }
+}
+
+// Bits that trigger something when written and always read as 0.
+fn write_only_bits(addr: usize) -> u4 {
+    match addr {
+        REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET => u4![0b0011],
+        REG_SWRST_SWRUN | REG_PROG_TIMER_RESET_ENABLE => u4![0b0010],
+        REG_BZSHOT_ENVRST_ENVRT_ENVON => u4![0b0100],
+        REG_SCTRG_SEN_SCS1_SCS0 => u4![0b1000],
+        _ => u4![0],
     }
 }
 
 pub const REG_CLOCK_INTERRUPT_FACTOR_FLAGS: usize = 0xF00;
-const REG_STOPWATCH_INTERRUPT_FACTOR_FLAGS: usize = 0xF01;
+pub const REG_STOPWATCH_INTERRUPT_FACTOR_FLAGS: usize = 0xF01;
 pub const REG_PROGRAMMABLE_TIMER_INTERRUPT_FACTOR_FLAGS: usize = 0xF02;
-const REG_SERIAL_INTERRUPT_FACTOR_FLAGS: usize = 0xF03;
-const REG_K00_K03_INTERRUPT_FACTOR_FLAGS: usize = 0xF04;
-const REG_K10_K13_INTERRUPT_FACTOR_FLAGS: usize = 0xF05;
+pub const REG_SERIAL_INTERRUPT_FACTOR_FLAGS: usize = 0xF03;
+pub const REG_K00_K03_INTERRUPT_FACTOR_FLAGS: usize = 0xF04;
+pub const REG_K10_K13_INTERRUPT_FACTOR_FLAGS: usize = 0xF05;
 
 // RW | Interrupt mask register (clock timer in Hz)
 pub const REG_EIT1_EIT2_EIT8_EIT32: usize = 0xF10;
 
 // RW | 0b0010 = Interrupt mask register (stopwatch 1 Hz) | 0b0001 = Interrupt mask register (stopwatch 10 Hz)
-const REG_EISW1_EISW0: usize = 0xF11;
+pub const REG_EISW1_EISW0: usize = 0xF11;
 
 // RW | 0b0001 = Interrupt mask register (programmable timer)
 pub const REG_EIPT: usize = 0xF12;
 
 // RW | 0b0001 = Interrupt mask register (serial interface)
-const REG_EISIO: usize = 0xF13;
+pub const REG_EISIO: usize = 0xF13;
 
 // RW | Interrupt mask register K03-K00
-const REG_EIK03_EIK02_EIK01_EIK00: usize = 0xF14;
+pub const REG_EIK03_EIK02_EIK01_EIK00: usize = 0xF14;
 
 // RW | Interrupt mask register K13-K10
-const REG_EIK13_EIK12_EIK11_EIK10: usize = 0xF15;
+pub const REG_EIK13_EIK12_EIK11_EIK10: usize = 0xF15;
+
+// R | Clock timer data (low-order)
+pub const REG_TM3_TM2_TM1_TM0: usize = 0xF20;
+
+// R | Clock timer data (high-order)
+pub const REG_TM7_TM6_TM5_TM4: usize = 0xF21;
+
+// R | Stopwatch timer data, 1/100 s digit in BCD
+pub const REG_SWL3_SWL2_SWL1_SWL0: usize = 0xF22;
+
+// R | Stopwatch timer data, 1/10 s digit in BCD
+pub const REG_SWH3_SWH2_SWH1_SWH0: usize = 0xF23;
 
 // RW | Programmable timer data (low-order)
 pub const REG_PROG_TIMER_DATA_LO: usize = 0xF24;
@@ -179,39 +255,111 @@ pub const REG_PROG_TIMER_RELOAD_DATA_LO: usize = 0xF26;
 // RW | Programmable timer reload data (high-order)
 pub const REG_PROG_TIMER_RELOAD_DATA_HI: usize = 0xF27;
 
+// RW | Serial interface data shift register (low-order)
+pub const REG_SD3_SD2_SD1_SD0: usize = 0xF30;
+
+// RW | Serial interface data shift register (high-order)
+pub const REG_SD7_SD6_SD5_SD4: usize = 0xF31;
+
 // R | Input port K03-K00
 pub const REG_K03_K02_K01_K00: usize = 0xF40;
 
+// RW | Input comparison register K03-K00. An interrupt is raised when an input stops matching it.
+pub const REG_DFK03_DFK02_DFK01_DFK00: usize = 0xF41;
+
+// R | Input port K13-K10
+pub const REG_K13_K12_K11_K10: usize = 0xF42;
+
 // RW | R43 = Output port (R43), Buzzer output (BZ) | R42 = Clock output (FOUT), [Buzzer inverted output (BZ)] | R40 = Clock inverted output (FOUT)
-const REG_R43_R42_R41_R40: usize = 0xF54;
+pub const REG_R43_R42_R41_R40: usize = 0xF54;
 
 // RW | 0b1000 = CPU system clock switch | 0b0100 = OSC3 oscillation On/Off | 0b0011 = CPU operating voltage switch
-const REG_CLKCHG_OSCC_VSC1_VSC0: usize = 0xF70;
+pub const REG_CLKCHG_OSCC_VSC1_VSC0: usize = 0xF70;
 
-// RW | All LCD dots fade out control | All LCD dots displayed control | LCD drive duty switch | Heavy load protection mode
-const REG_ALOFF_ALON_LDUTY_HLMOD: usize = 0xF71;
+// RW | 0b1000 = All LCD dots fade out control | 0b0100 = All LCD dots displayed control | 0b0010 = LCD drive duty switch (1 = 1/8, 0 = 1/16) | 0b0001 = Heavy load protection mode
+pub const REG_ALOFF_ALON_LDUTY_HLMOD: usize = 0xF71;
 
-// LCD contrast adjustment
-const REG_LC3_LC2_LC1_LC0: usize = 0xF72;
+// RW | LCD contrast adjustment, 0 = lightest to 15 = darkest
+pub const REG_LC3_LC2_LC1_LC0: usize = 0xF72;
 
 // Supply voltage detection
 // R | 0b1000 = SVD evaluation data. 1 means Low, 0 means Normal.
 // RW | 0b0100 SVD circuit On/Off | 0b0011 = SVD criteria voltage setting
-const REG_SVDDT_SVDON_SVC1_SVC0: usize = 0xF73;
+pub const REG_SVDDT_SVDON_SVC1_SVC0: usize = 0xF73;
 
 // RW | 0b1000 = 1-shot buzzer pulse width | 0b0111 = Buzzer frequency selection
-const REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0: usize = 0xF74;
+pub const REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0: usize = 0xF74;
 
-const REG_BZSHOT_ENVRST_ENVRT_ENVON: usize = 0xF75;
+pub const REG_BZSHOT_ENVRST_ENVRT_ENVON: usize = 0xF75;
 
 // W | 0b0010 = TMRST = Clock timer reset | 0b0001 = WDRST = Watchdog timer reset
-const REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET: usize = 0xF76;
+pub const REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET: usize = 0xF76;
 
 // W | 0b0010 = SWRST = Stopwatch timer reset | 0b0001 = SWRUN = Stopwatch timer Run/Stop
-const REG_SWRST_SWRUN: usize = 0xF77;
+pub const REG_SWRST_SWRUN: usize = 0xF77;
 
 // W | 0b0010 = SWRST = Programmable timer reset | 0b0001 = SWRUN = Programmable timer Run/Stop
 pub const REG_PROG_TIMER_RESET_ENABLE: usize = 0xF78;
 
 // RW | 0b0010 = Programmable timer clock output | 0b0111 = Programmable timer input clock selection
-const REG_PTCOUT_PTC2_PTC1_PTC0: usize = 0xF79;
+pub const REG_PTCOUT_PTC2_PTC1_PTC0: usize = 0xF79;
+
+// W | 0b1000 = SCTRG = Serial interface clock trigger
+// RW | 0b0100 = SEN = SCLK edge selection | 0b0011 = SCS = Clock source (0 = slave, 1 = programmable timer, 2 = OSC1/2, 3 = OSC1)
+pub const REG_SCTRG_SEN_SCS1_SCS0: usize = 0xF7A;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_io() {
+        let mut memory = Memory::new();
+        memory.set(0xF7E, u4![0x5]);
+        assert_eq!(memory.take_error(), Some(EmulationError::UnknownIoWrite { addr: 0xF7E, value: u4![0x5] }));
+        assert_eq!(memory.bytes.borrow()[0xF7E], u4![0]);
+
+        assert_eq!(memory.get(0xF7E), u4![0]);
+        assert_eq!(memory.take_error(), Some(EmulationError::UnknownIoRead { addr: 0xF7E }));
+        assert_eq!(memory.take_error(), None);
+    }
+
+    #[test]
+    fn slice_peeks() {
+        let mut memory = Memory::new();
+        memory.interrupts.raise(Interrupt::ClockTimer, u4![0b0100]);
+        memory.set(REG_SWRST_SWRUN, u4![0b0011]);
+
+        let io = memory.slice(REG_CLOCK_INTERRUPT_FACTOR_FLAGS..REG_SWRST_SWRUN + 1);
+        assert_eq!((io[0], io[REG_SWRST_SWRUN - REG_CLOCK_INTERRUPT_FACTOR_FLAGS]), (u4![0b0100], u4![0b0001]));
+        assert_eq!(memory.get(REG_CLOCK_INTERRUPT_FACTOR_FLAGS), u4![0b0100]);
+        assert_eq!(memory.take_error(), None);
+    }
+
+    #[test]
+    fn memory_map() {
+        let diagnostics = Rc::new(RefCell::new(vec![]));
+        let mut memory = Memory::new();
+        memory.diagnostics = Some(Rc::new({
+            let diagnostics = diagnostics.clone();
+            move |diagnostic| diagnostics.borrow_mut().push(diagnostic)
+        }));
+
+        memory.set(0x27F, u4![0x3]);
+        memory.set(0x280, u4![0x3]);
+        memory.set(REG_K03_K02_K01_K00, u4![0x0]);
+        memory.set(REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET, u4![0b0011]);
+        memory.set(REG_SVDDT_SVDON_SVC1_SVC0, u4![0b1011]);
+
+        assert_eq!(memory.get(0x27F), u4![0x3]);
+        assert_eq!(memory.bytes.borrow()[REG_SVDDT_SVDON_SVC1_SVC0], u4![0b0011]);
+        assert_eq!(memory.get(0xE50), UNMAPPED_VALUE);
+        assert_eq!(memory.get(REG_K03_K02_K01_K00), u4![0b1111]);
+        assert_eq!(memory.get(REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET), u4![0]);
+        assert_eq!(*diagnostics.borrow(), vec![
+            Diagnostic::UnmappedWrite { addr: 0x280, value: u4![0x3] },
+            Diagnostic::ReadOnlyWrite { addr: REG_K03_K02_K01_K00, value: u4![0x0] },
+            Diagnostic::UnmappedRead { addr: 0xE50 },
+        ]);
+    }
+}
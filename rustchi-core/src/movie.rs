@@ -0,0 +1,265 @@
+use std::fmt;
+
+use crate::{
+    error::EmulationError,
+    input::Button,
+    interpreter::Interpreter,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const MAGIC: &[u8; 8] = b"RUSTCHIM";
+const VERSION: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    NotAMovie,
+    UnsupportedVersion(u8),
+    /// The movie was recorded with another ROM, by hash.
+    WrongRom { expected: u64, found: u64 },
+    SaveState(SaveStateError),
+    Emulation(EmulationError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => write!(f, "unsupported movie version {}", version),
+            MovieError::WrongRom { expected, found } =>
+                write!(f, "movie was recorded with ROM {:016X}, this one is {:016X}", found, expected),
+            MovieError::SaveState(error) => write!(f, "invalid movie: {}", error),
+            MovieError::Emulation(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(error: SaveStateError) -> Self {
+        MovieError::SaveState(error)
+    }
+}
+
+impl From<EmulationError> for MovieError {
+    fn from(error: EmulationError) -> Self {
+        MovieError::Emulation(error)
+    }
+}
+
+/// A button pressed or released, some cycles into the movie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieEvent {
+    /// OSC1 cycles since the start state. Unlike `State::cycles`, it doesn't wrap.
+    pub cycles: u64,
+    pub button: Button,
+    pub pressed: bool,
+}
+
+/// The inputs given to a ROM from a save state. Replaying them reproduces the same emulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub start: Vec<u8>,
+    pub events: Vec<MovieEvent>,
+    /// OSC1 cycles from the start state to where the recording stopped.
+    pub end: u64,
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        MAGIC.iter().for_each(|byte| writer.u8(*byte));
+        writer.u8(VERSION);
+        writer.u64(self.rom_hash);
+        writer.bytes(&self.start);
+
+        writer.u32(self.events.len().try_into().unwrap());
+        for event in &self.events {
+            writer.u64(event.cycles);
+            writer.u8(match event.button {
+                Button::A => 0,
+                Button::B => 1,
+                Button::C => 2,
+            });
+            writer.bool(event.pressed);
+        }
+        writer.u64(self.end);
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or(MovieError::NotAMovie)?;
+        let mut reader = StateReader::new(rest);
+
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let rom_hash = reader.u64()?;
+        let start = reader.bytes()?.to_vec();
+        let events = (0..reader.u32()?).map(|_| {
+            let cycles = reader.u64()?;
+            let button = match reader.u8()? {
+                0 => Button::A,
+                1 => Button::B,
+                2 => Button::C,
+                _ => return Err(SaveStateError::Corrupt("button")),
+            };
+            let pressed = reader.bool("button state")?;
+            Ok(MovieEvent { cycles, button, pressed })
+        }).collect::<Result<_, _>>()?;
+        let end = reader.u64()?;
+
+        Ok(Self { rom_hash, start, events, end })
+    }
+}
+
+// OSC1 cycles elapsed since the start, following `State::cycles` across wraps
+#[derive(Debug, Clone)]
+pub(crate) struct Elapsed {
+    last: u32,
+    total: u64,
+}
+
+impl Elapsed {
+    pub(crate) fn new(interpreter: &Interpreter) -> Self {
+        Self { last: interpreter.state.cycles, total: 0 }
+    }
+
+    pub(crate) fn update(&mut self, interpreter: &Interpreter) -> u64 {
+        let now = interpreter.state.cycles;
+        self.total += u64::from(now.wrapping_sub(self.last));
+        self.last = now;
+        self.total
+    }
+}
+
+/// Records the buttons pressed and released from the interpreter's current state on.
+pub struct MovieRecorder {
+    movie: Movie,
+    elapsed: Elapsed,
+}
+
+impl MovieRecorder {
+    pub fn new(interpreter: &Interpreter) -> Self {
+        Self {
+            movie: Movie { rom_hash: interpreter.rom.hash(), start: interpreter.save_state(), events: vec![], end: 0 },
+            elapsed: Elapsed::new(interpreter),
+        }
+    }
+
+    /// Presses the button and records it.
+    pub fn press(&mut self, interpreter: &mut Interpreter, button: Button) {
+        self.push(interpreter, button, true);
+        interpreter.press_button(button);
+    }
+
+    /// Releases the button and records it.
+    pub fn release(&mut self, interpreter: &mut Interpreter, button: Button) {
+        self.push(interpreter, button, false);
+        interpreter.release_button(button);
+    }
+
+    fn push(&mut self, interpreter: &Interpreter, button: Button, pressed: bool) {
+        let cycles = self.elapsed.update(interpreter);
+        self.movie.events.push(MovieEvent { cycles, button, pressed });
+    }
+
+    /// Ends the movie where the interpreter is now.
+    pub fn finish(mut self, interpreter: &Interpreter) -> Movie {
+        self.movie.end = self.elapsed.update(interpreter);
+        self.movie
+    }
+}
+
+/// Feeds the buttons of a movie to an interpreter as it runs.
+pub struct MoviePlayer {
+    movie: Movie,
+    next: usize,
+    elapsed: Elapsed,
+    now: u64,
+}
+
+impl MoviePlayer {
+    /// Loads the movie's start state into the interpreter, which must run the ROM it was recorded with.
+    pub fn start(interpreter: &mut Interpreter, movie: Movie) -> Result<Self, MovieError> {
+        let rom_hash = interpreter.rom.hash();
+        if movie.rom_hash != rom_hash {
+            return Err(MovieError::WrongRom { expected: rom_hash, found: movie.rom_hash });
+        }
+
+        interpreter.load_state(&movie.start)?;
+        let elapsed = Elapsed::new(interpreter);
+        Ok(Self { movie, next: 0, elapsed, now: 0 })
+    }
+
+    /// Applies the events that are due. Call it before every step.
+    pub fn update(&mut self, interpreter: &mut Interpreter) {
+        let now = self.elapsed.update(interpreter);
+        self.now = now;
+        while let Some(event) = self.movie.events.get(self.next).filter(|event| event.cycles <= now) {
+            if event.pressed {
+                interpreter.press_button(event.button);
+            } else {
+                interpreter.release_button(event.button);
+            }
+            self.next += 1;
+        }
+    }
+
+    /// Whether every event was applied and the emulation reached the end of the recording.
+    pub fn is_finished(&self) -> bool {
+        self.next == self.movie.events.len() && self.now >= self.movie.end
+    }
+
+    /// Runs the interpreter to the end of the recording.
+    pub fn play(interpreter: &mut Interpreter, movie: Movie) -> Result<(), MovieError> {
+        let mut player = Self::start(interpreter, movie)?;
+        loop {
+            player.update(interpreter);
+            if player.is_finished() {
+                return Ok(());
+            }
+            interpreter.step()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{chip::ChipProfile, fixture};
+
+    #[test]
+    fn replay() {
+        let mut interpreter = fixture::counter(ChipProfile::e0c6s46());
+
+        let run = |interpreter: &mut Interpreter, steps| (0..steps).for_each(|_| { interpreter.step().unwrap(); });
+        run(&mut interpreter, 100);
+
+        let mut recorder = MovieRecorder::new(&interpreter);
+        for (steps, button, pressed) in [(50, Button::A, true), (200, Button::C, true), (7, Button::A, false), (1000, Button::C, false)] {
+            run(&mut interpreter, steps);
+            if pressed {
+                recorder.press(&mut interpreter, button);
+            } else {
+                recorder.release(&mut interpreter, button);
+            }
+        }
+        // The recording goes on after the last event
+        run(&mut interpreter, 500);
+        let movie = Movie::from_bytes(&recorder.finish(&interpreter).to_bytes()).unwrap();
+        let expected = (interpreter.state.cycles, interpreter.pc(), interpreter.state.memory.slice(0..4096));
+
+        let mut replayed = fixture::counter(ChipProfile::e0c6s46());
+        MoviePlayer::play(&mut replayed, movie.clone()).unwrap();
+        assert_eq!((replayed.state.cycles, replayed.pc(), replayed.state.memory.slice(0..4096)), expected);
+
+        let mut other = fixture::counter(ChipProfile::e0c6s46());
+        other.rom.words[0x200] = 0x000;
+        assert!(matches!(MoviePlayer::start(&mut other, movie), Err(MovieError::WrongRom { .. })));
+        assert_eq!(Movie::from_bytes(b"RUSTCHI\0"), Err(MovieError::NotAMovie));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    memory::REG_CLKCHG_OSCC_VSC1_VSC0,
+    peripheral::Peripheral,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+pub const OSC1_CLOCK: u32 = 32_768;
+pub const OSC3_CLOCK: u32 = 1_000_000;
+
+/// Oscillation circuits, and the switch picking the one driving the CPU.
+#[derive(Clone)]
+pub struct Oscillator {
+    // Last value written to CLKCHG/OSCC/VSC1/VSC0
+    control: u4,
+}
+
+impl Oscillator {
+    pub fn new() -> Self {
+        Self {
+            control: u4![0],
+        }
+    }
+
+    /// CPU clock in Hz. OSC3 only drives the CPU while its oscillator is on.
+    pub fn cpu_clock(&self) -> u32 {
+        if self.control.is_set(u4![0b1100]) { OSC3_CLOCK } else { OSC1_CLOCK }
+    }
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Peripheral for Oscillator {
+    fn handles(&self, addr: usize) -> bool {
+        addr == REG_CLKCHG_OSCC_VSC1_VSC0
+    }
+
+    fn write(&mut self, _addr: usize, value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        self.control = value;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u4(self.control);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.control = reader.u4("oscillator control")?;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,219 @@
+use std::any::Any;
+
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    input::InputPorts,
+    interrupt::{Interrupt, InterruptController, InterruptMasks},
+    lcd::LcdController,
+    osc::Oscillator,
+    savestate::{SaveStateError, StateReader, StateWriter},
+    serial::Serial,
+    stopwatch::Stopwatch,
+    buzzer::Buzzer,
+    chip::ChipProfile,
+    svd::Svd,
+    timer::{ClockTimer, ProgTimer},
+    watchdog::Watchdog,
+};
+
+/// A hardware block mapped on the I/O registers.
+///
+/// `registers` is the whole data memory, indexed by address. It holds the last value the ROM wrote
+/// to each register, which peripherals are free to update.
+pub trait Peripheral: Any {
+    fn handles(&self, addr: usize) -> bool;
+
+    /// Value seen by the ROM when reading one of the registers.
+    fn read(&self, addr: usize, registers: &[u4]) -> Result<u4, EmulationError> {
+        Ok(registers[addr])
+    }
+
+    /// Called after `value` was stored in `registers`.
+    fn write(&mut self, _addr: usize, _value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        Ok(())
+    }
+
+    /// Advances by a number of OSC1 cycles.
+    fn tick(&mut self, _delta_cycles: u32, _registers: &mut [u4]) {}
+
+    /// Interrupt factor flags to raise, polled after every write and tick until it returns `None`.
+    fn interrupt_request(&mut self) -> Option<(Interrupt, u4)> {
+        None
+    }
+
+    /// Initial reset. Configuration that isn't part of the chip, like links or batteries, is kept.
+    fn reset(&mut self) {}
+
+    /// Writes what a save state needs to restore the peripheral, leaving the configuration out like `reset` does.
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<(), SaveStateError> {
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral>;
+}
+
+impl Clone for Box<dyn Peripheral> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Routes the I/O registers to the peripherals handling them.
+#[derive(Clone, Default)]
+pub struct Bus {
+    peripherals: Vec<Box<dyn Peripheral>>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The peripherals of the E0C6S46. They tick in this order.
+    pub fn e0c6s46(profile: &ChipProfile) -> Self {
+        let mut bus = Self::new();
+        bus.register(InterruptMasks::new());
+        bus.register(Oscillator::new());
+        bus.register(ClockTimer::new());
+        bus.register(Stopwatch::new());
+        bus.register(ProgTimer::new());
+        bus.register(Serial::new());
+        bus.register(InputPorts::new());
+        bus.register(Svd::new());
+        bus.register(Buzzer::new());
+        bus.register(LcdController::new(profile));
+        bus.register(Watchdog::new());
+        bus
+    }
+
+    pub fn register(&mut self, peripheral: impl Peripheral) {
+        self.peripherals.push(Box::new(peripheral));
+    }
+
+    /// Takes a peripheral off the bus, to swap in a different implementation.
+    pub fn remove<P: Peripheral>(&mut self) -> Option<Box<dyn Peripheral>> {
+        let index = self.peripherals.iter().position(|peripheral| (peripheral.as_ref() as &dyn Any).is::<P>())?;
+        Some(self.peripherals.remove(index))
+    }
+
+    pub fn get<P: Peripheral>(&self) -> Option<&P> {
+        self.peripherals.iter().find_map(|peripheral| (peripheral.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub fn get_mut<P: Peripheral>(&mut self) -> Option<&mut P> {
+        self.peripherals.iter_mut().find_map(|peripheral| (peripheral.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    pub fn handles(&self, addr: usize) -> bool {
+        self.peripherals.iter().any(|peripheral| peripheral.handles(addr))
+    }
+
+    /// Reads from the first peripheral handling the register.
+    pub fn read(&self, addr: usize, registers: &[u4]) -> Option<Result<u4, EmulationError>> {
+        self.peripherals.iter()
+            .find(|peripheral| peripheral.handles(addr))
+            .map(|peripheral| peripheral.read(addr, registers))
+    }
+
+    /// Writes to every peripheral handling the register, as some registers mix bits of several blocks.
+    pub fn write(&mut self, addr: usize, value: u4, registers: &mut [u4], interrupts: &mut InterruptController) -> Option<Result<(), EmulationError>> {
+        let mut result = None;
+        for peripheral in self.peripherals.iter_mut().filter(|peripheral| peripheral.handles(addr)) {
+            let written = peripheral.write(addr, value, registers);
+            raise_requests(peripheral.as_mut(), interrupts);
+            result = Some(result.unwrap_or(Ok(())).and(written));
+        }
+        result
+    }
+
+    pub fn tick(&mut self, delta_cycles: u32, registers: &mut [u4], interrupts: &mut InterruptController) {
+        for peripheral in self.peripherals.iter_mut() {
+            peripheral.tick(delta_cycles, registers);
+            raise_requests(peripheral.as_mut(), interrupts);
+        }
+    }
+
+    /// Raises the requests peripherals made outside of `write` and `tick`.
+    pub fn poll(&mut self, interrupts: &mut InterruptController) {
+        for peripheral in self.peripherals.iter_mut() {
+            raise_requests(peripheral.as_mut(), interrupts);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.peripherals.iter_mut().for_each(|peripheral| peripheral.reset());
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.peripherals.len().try_into().unwrap());
+        for peripheral in &self.peripherals {
+            let mut state = StateWriter::new();
+            peripheral.save_state(&mut state);
+            writer.bytes(&state.into_bytes());
+        }
+    }
+
+    /// Restores the peripherals from a state saved by a bus with the same peripherals, in the same order.
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        if reader.u32()? as usize != self.peripherals.len() {
+            return Err(SaveStateError::Corrupt("peripheral count"));
+        }
+        for peripheral in self.peripherals.iter_mut() {
+            peripheral.load_state(&mut StateReader::new(reader.bytes()?))?;
+        }
+        Ok(())
+    }
+}
+
+fn raise_requests(peripheral: &mut dyn Peripheral, interrupts: &mut InterruptController) {
+    while let Some((interrupt, factor)) = peripheral.interrupt_request() {
+        match interrupt {
+            Interrupt::Watchdog => interrupts.raise_nmi(),
+            _ => interrupts.raise(interrupt, factor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0;
+
+    #[derive(Clone, Default)]
+    struct Beeper {
+        writes: Vec<u4>,
+    }
+
+    impl Peripheral for Beeper {
+        fn handles(&self, addr: usize) -> bool {
+            addr == REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0
+        }
+
+        fn write(&mut self, _addr: usize, value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+            self.writes.push(value);
+            Ok(())
+        }
+
+        fn clone_box(&self) -> Box<dyn Peripheral> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn swap_peripheral() {
+        let mut bus = Bus::e0c6s46(&ChipProfile::e0c6s46());
+        let mut registers = [u4![0]; 4096];
+        let mut interrupts = InterruptController::new();
+
+        assert!(bus.remove::<Buzzer>().is_some());
+        assert!(bus.get::<Buzzer>().is_none());
+        bus.register(Beeper::default());
+
+        assert_eq!(bus.write(REG_SHOTPW_BZFQ2_BZFQ1_BZFQ0, u4![0x3], &mut registers, &mut interrupts), Some(Ok(())));
+        assert_eq!(bus.get::<Beeper>().unwrap().writes, vec![u4![0x3]]);
+        assert_eq!(bus.write(0xF7E, u4![0x3], &mut registers, &mut interrupts), None);
+    }
+}
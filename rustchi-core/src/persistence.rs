@@ -0,0 +1,102 @@
+/// Default share of the previous frame kept in the next one, out of 256.
+pub const DEFAULT_LCD_PERSISTENCE: u8 = 96;
+
+/// Integrates the LCD picture over time, the way the liquid crystal averages what it's driven with.
+///
+/// Each dot's intensity is weighted by how many cycles it was shown during the frame, then blended
+/// with the previous frame so that the dots fade in and out instead of flickering.
+#[derive(Clone)]
+pub struct LcdPersistence {
+    /// Share of the previous frame kept in the next one, out of 256. 0 disables the blending.
+    pub persistence: u8,
+    current: [[u8; 40]; 16],
+    weighted: [[u64; 40]; 16],
+    pending_cycles: u32,
+    frame_cycles: u32,
+    output: [[u8; 40]; 16],
+}
+
+impl LcdPersistence {
+    pub fn new() -> Self {
+        Self {
+            persistence: DEFAULT_LCD_PERSISTENCE,
+            current: [[0; 40]; 16],
+            weighted: [[0; 40]; 16],
+            pending_cycles: 0,
+            frame_cycles: 0,
+            output: [[0; 40]; 16],
+        }
+    }
+
+    /// Called whenever the instantaneous picture changes.
+    pub fn update(&mut self, intensity: [[u8; 40]; 16]) {
+        self.flush();
+        self.current = intensity;
+    }
+
+    pub fn tick(&mut self, delta_cycles: u32) {
+        self.pending_cycles += delta_cycles;
+        self.frame_cycles += delta_cycles;
+    }
+
+    // Accumulating lazily keeps the per-instruction cost down, the picture rarely changes.
+    fn flush(&mut self) {
+        if self.pending_cycles == 0 {
+            return;
+        }
+
+        for (weighted, current) in self.weighted.iter_mut().flatten().zip(self.current.iter().flatten()) {
+            *weighted += u64::from(*current) * u64::from(self.pending_cycles);
+        }
+        self.pending_cycles = 0;
+    }
+
+    /// Ends the frame and returns the blended intensity of each dot, from 0 to 255.
+    pub fn take_frame(&mut self) -> [[u8; 40]; 16] {
+        if self.frame_cycles == 0 {
+            return self.output;
+        }
+
+        self.flush();
+
+        let frame_cycles = u64::from(self.frame_cycles);
+        let persistence = u64::from(self.persistence);
+        for (output, weighted) in self.output.iter_mut().flatten().zip(self.weighted.iter_mut().flatten()) {
+            let average = *weighted / frame_cycles;
+            *output = ((average * (256 - persistence) + u64::from(*output) * persistence) / 256) as u8;
+            *weighted = 0;
+        }
+        self.frame_cycles = 0;
+
+        self.output
+    }
+}
+
+impl Default for LcdPersistence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blending() {
+        let mut lcd = LcdPersistence::new();
+        lcd.persistence = 0;
+
+        lcd.update([[255; 40]; 16]);
+        lcd.tick(100);
+        lcd.update([[0; 40]; 16]);
+        lcd.tick(300);
+        assert_eq!(lcd.take_frame()[0][0], 63);
+
+        lcd.persistence = 128;
+        lcd.update([[255; 40]; 16]);
+        lcd.tick(400);
+        assert_eq!(lcd.take_frame()[0][0], 159);
+        assert_eq!(lcd.take_frame()[0][0], 159);
+    }
+}
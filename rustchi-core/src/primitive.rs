@@ -168,7 +168,7 @@ impl u4 {
     pub const MIN: Self = Self(0x0);
     pub const MAX: Self = Self(0xF);
 
-    pub fn new(value: u8) -> Self {
+    pub const fn new(value: u8) -> Self {
         Self(value)
     }
 
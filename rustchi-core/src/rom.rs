@@ -0,0 +1,246 @@
+use std::fmt;
+
+use crate::chip::ChipProfile;
+
+/// Ways 12-bit program words are stored in the dumps going around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomFormat {
+    /// One word per 2 bytes, high byte first, like the `rom.bin` tamatool reads.
+    Words16BigEndian,
+    Words16LittleEndian,
+    /// Two words per 3 bytes, most significant nibble first.
+    Packed12,
+    /// tamalib's generated `rom.h`, a C array of `u12_t`.
+    TamalibHeader,
+}
+
+impl RomFormat {
+    /// Formats the bytes could be in, most likely first. Binary dumps can pass for text, so they're
+    /// tried after the header too.
+    pub fn candidates(bytes: &[u8]) -> Vec<Self> {
+        let mut formats = vec![];
+        if looks_like_header(bytes) {
+            formats.push(RomFormat::TamalibHeader);
+        }
+        if bytes.len().is_multiple_of(2) {
+            // The unused top nibble of every word is 0
+            if bytes.iter().step_by(2).all(|byte| byte >> 4 == 0) {
+                formats.push(RomFormat::Words16BigEndian);
+            }
+            if bytes.iter().skip(1).step_by(2).all(|byte| byte >> 4 == 0) {
+                formats.push(RomFormat::Words16LittleEndian);
+            }
+        }
+        if bytes.len().is_multiple_of(3) {
+            formats.push(RomFormat::Packed12);
+        }
+        formats
+    }
+}
+
+impl fmt::Display for RomFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RomFormat::Words16BigEndian => "16-bit big endian words",
+            RomFormat::Words16LittleEndian => "16-bit little endian words",
+            RomFormat::Packed12 => "packed 12-bit words",
+            RomFormat::TamalibHeader => "tamalib C header",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    /// None of the formats fit the file.
+    UnknownFormat { len: usize },
+    InvalidLength { format: RomFormat, len: usize },
+    /// A word doesn't fit in 12 bits.
+    InvalidWord { format: RomFormat, index: usize, word: u32 },
+    InvalidHeader { reason: String },
+    WrongSize { format: RomFormat, words: usize, chip: &'static str, expected: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::UnknownFormat { len } =>
+                write!(f, "unrecognized ROM format ({} bytes): not a tamalib header, 16-bit words or packed 12-bit words", len),
+            RomError::InvalidLength { format, len } =>
+                write!(f, "{} bytes can't hold {}", len, format),
+            RomError::InvalidWord { format, index, word } =>
+                write!(f, "word {} is {:#X}, which doesn't fit in 12 bits, as {}", index, word, format),
+            RomError::InvalidHeader { reason } =>
+                write!(f, "invalid tamalib header: {}", reason),
+            RomError::WrongSize { format, words, chip, expected } =>
+                write!(f, "ROM has {} words as {}, the {} has {}", words, format, chip, expected),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// A program in 12-bit words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom {
+    pub words: Vec<u16>,
+    pub format: RomFormat,
+}
+
+impl Rom {
+    /// Detects the format of a dump and checks that it fills the chip's program memory.
+    pub fn load(bytes: &[u8], profile: &ChipProfile) -> Result<Self, RomError> {
+        let mut first_error = None;
+
+        for format in RomFormat::candidates(bytes) {
+            match Self::load_as(bytes, format, profile) {
+                Ok(rom) => return Ok(rom),
+                Err(error) => { first_error.get_or_insert(error); },
+            }
+        }
+
+        Err(first_error.unwrap_or(RomError::UnknownFormat { len: bytes.len() }))
+    }
+
+    pub fn load_as(bytes: &[u8], format: RomFormat, profile: &ChipProfile) -> Result<Self, RomError> {
+        let rom = Self::parse(bytes, format)?;
+        if rom.words.len() != profile.rom_words {
+            return Err(RomError::WrongSize { format, words: rom.words.len(), chip: profile.name, expected: profile.rom_words });
+        }
+        Ok(rom)
+    }
+
+    /// Reads the words without checking their count.
+    pub fn parse(bytes: &[u8], format: RomFormat) -> Result<Self, RomError> {
+        let words: Vec<u32> = match format {
+            RomFormat::Words16BigEndian | RomFormat::Words16LittleEndian if !bytes.len().is_multiple_of(2) =>
+                return Err(RomError::InvalidLength { format, len: bytes.len() }),
+            RomFormat::Words16BigEndian => bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]).into()).collect(),
+            RomFormat::Words16LittleEndian => bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]).into()).collect(),
+            RomFormat::Packed12 if !bytes.len().is_multiple_of(3) =>
+                return Err(RomError::InvalidLength { format, len: bytes.len() }),
+            RomFormat::Packed12 => bytes.chunks_exact(3).flat_map(|triple| {
+                let [a, b, c] = [triple[0], triple[1], triple[2]].map(u32::from);
+                [(a << 4) | (b >> 4), ((b & 0xF) << 8) | c]
+            }).collect(),
+            RomFormat::TamalibHeader => parse_header(bytes)?,
+        };
+
+        let words = words.into_iter().enumerate().map(|(index, word)| {
+            u16::try_from(word).ok().filter(|word| *word <= 0xFFF).ok_or(RomError::InvalidWord { format, index, word })
+        }).collect::<Result<_, _>>()?;
+
+        Ok(Self { words, format })
+    }
+
+    /// FNV-1a hash of the words, whatever format they were read from.
+    pub fn hash(&self) -> u64 {
+        self.words.iter().flat_map(|word| word.to_be_bytes()).fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01B3)
+        })
+    }
+}
+
+fn looks_like_header(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).is_ok_and(|text| text.contains('{') && text.contains("0x"))
+}
+
+// Values between the first pair of braces, in hexadecimal or decimal, with C comments.
+fn parse_header(bytes: &[u8]) -> Result<Vec<u32>, RomError> {
+    let invalid = |reason: String| RomError::InvalidHeader { reason };
+
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid("not UTF-8 text".to_string()))?;
+    let text = strip_comments(text);
+    let start = text.find('{').ok_or_else(|| invalid("no `{` opening the array".to_string()))?;
+    let end = text[start..].find('}').ok_or_else(|| invalid("no `}` closing the array".to_string()))?;
+
+    text[start + 1..start + end].split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => value.parse(),
+            };
+            parsed.map_err(|_| invalid(format!("`{}` isn't a number", value)))
+        })
+        .collect()
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('/') {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+        } else if rest.starts_with("/*") {
+            rest = rest.find("*/").map_or("", |end| &rest[end + 2..]);
+        } else {
+            stripped.push('/');
+            rest = &rest[1..];
+        }
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The profile's size, starting with a few words that tell the formats apart
+    fn words(profile: &ChipProfile) -> Vec<u16> {
+        let mut words = vec![0; profile.rom_words];
+        words[..4].copy_from_slice(&[0xFFF, 0x123, 0x0AB, 0x000]);
+        words
+    }
+
+    #[test]
+    fn formats() {
+        for profile in ChipProfile::all() {
+            let words = words(&profile);
+
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+            let rom = Rom::load(&bytes, &profile).unwrap();
+            assert_eq!((&rom.words, rom.format), (&words, RomFormat::Words16BigEndian));
+
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+            let rom = Rom::load(&bytes, &profile).unwrap();
+            assert_eq!((&rom.words, rom.format), (&words, RomFormat::Words16LittleEndian));
+
+            let bytes: Vec<u8> = words.chunks(2).flat_map(|pair| {
+                let [a, b] = [pair[0], pair[1]];
+                [(a >> 4) as u8, ((a & 0xF) << 4 | b >> 8) as u8, b as u8]
+            }).collect();
+            assert_eq!(bytes.len(), profile.rom_words * 3 / 2);
+            let rom = Rom::load(&bytes, &profile).unwrap();
+            assert_eq!((&rom.words, rom.format), (&words, RomFormat::Packed12));
+
+            let values = words.iter().map(|word| format!("{:#05X}", word)).collect::<Vec<_>>().join(", /* x */ ");
+            let header = format!("// generated\nstatic const u12_t g_program[] = {{\n\t{},\n}};\n", values);
+            let rom = Rom::load(header.as_bytes(), &profile).unwrap();
+            assert_eq!((&rom.words, rom.format), (&words, RomFormat::TamalibHeader));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let s46 = ChipProfile::e0c6s46();
+        assert_eq!(Rom::load(&[0xFF; 5], &s46), Err(RomError::UnknownFormat { len: 5 }));
+        assert_eq!(Rom::load(&[0x00; 8192], &s46), Err(RomError::WrongSize { format: RomFormat::Words16BigEndian, words: 4096, chip: "E0C6S46", expected: 6144 }));
+        assert_eq!(Rom::load(b"{ 0x1000 }", &s46), Err(RomError::InvalidWord { format: RomFormat::TamalibHeader, index: 0, word: 0x1000 }));
+        assert!(matches!(Rom::load(b"{ 0x1, zero }", &s46), Err(RomError::InvalidHeader { .. })));
+    }
+
+    #[test]
+    fn binary_looking_like_text() {
+        let s48 = ChipProfile::e0c6s48();
+        let bytes = b"{0x".repeat(s48.rom_words / 2);
+        assert_eq!(RomFormat::candidates(&bytes), vec![RomFormat::TamalibHeader, RomFormat::Packed12]);
+        assert_eq!(Rom::load(&bytes, &s48).map(|rom| rom.format), Ok(RomFormat::Packed12));
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+
+use crate::primitive::{u1, u4, u12};
+
+const MAGIC: &[u8; 8] = b"RUSTCHI\0";
+const VERSION: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion(u8),
+    WrongChip { expected: &'static str, found: String },
+    /// The data ended before all the fields were read.
+    Truncated,
+    /// A field holds a value it can't have, naming the field.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(f, "unsupported save state version {}", version),
+            SaveStateError::WrongChip { expected, found } => write!(f, "save state is for the {}, not the {}", found, expected),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Corrupt(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+/// Appends the fields of a save state, little endian.
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts with the magic, the format version and the chip the state is for.
+    pub(crate) fn with_header(chip: &str) -> Self {
+        let mut writer = Self::new();
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u8(VERSION);
+        writer.bytes(chip.as_bytes());
+        writer
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value.into());
+    }
+
+    pub fn u1(&mut self, value: u1) {
+        self.u8(value.into());
+    }
+
+    pub fn u4(&mut self, value: u4) {
+        self.u8(value.into());
+    }
+
+    pub fn u12(&mut self, value: u12) {
+        self.bytes.extend_from_slice(&u16::from(value).to_le_bytes());
+    }
+
+    /// Length prefixed bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len().try_into().unwrap());
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+/// Reads the fields back in the order they were written.
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Checks the header written by `StateWriter::with_header`.
+    pub(crate) fn with_header(bytes: &'a [u8], chip: &'static str) -> Result<Self, SaveStateError> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or(SaveStateError::NotASaveState)?;
+        let mut reader = Self::new(rest);
+
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        let found = String::from_utf8_lossy(reader.bytes()?).into_owned();
+        if found != chip {
+            return Err(SaveStateError::WrongChip { expected: chip, found });
+        }
+
+        Ok(reader)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let (head, rest) = self.bytes.split_first_chunk().ok_or(SaveStateError::Truncated)?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    pub fn bool(&mut self, field: &'static str) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupt(field)),
+        }
+    }
+
+    pub fn u1(&mut self, field: &'static str) -> Result<u1, SaveStateError> {
+        self.u8()?.try_into().map_err(|_| SaveStateError::Corrupt(field))
+    }
+
+    pub fn u4(&mut self, field: &'static str) -> Result<u4, SaveStateError> {
+        self.u8()?.try_into().map_err(|_| SaveStateError::Corrupt(field))
+    }
+
+    pub fn u12(&mut self, field: &'static str) -> Result<u12, SaveStateError> {
+        u16::from_le_bytes(self.take()?).try_into().map_err(|_| SaveStateError::Corrupt(field))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], SaveStateError> {
+        let len = self.u32()? as usize;
+        if self.bytes.len() < len {
+            return Err(SaveStateError::Truncated);
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{chip::ChipProfile, fixture, interpreter::Interpreter};
+
+    #[test]
+    fn round_trip() {
+        let mut interpreter = fixture::counter(ChipProfile::e0c6s46());
+
+        for _ in 0..1000 {
+            interpreter.step().unwrap();
+        }
+        let saved = interpreter.save_state();
+
+        let run = |interpreter: &mut Interpreter| {
+            (0..5000).for_each(|_| { interpreter.step().unwrap(); });
+            (interpreter.state.cycles, interpreter.pc(), interpreter.state.memory.slice(0..4096))
+        };
+        let expected = run(&mut interpreter);
+
+        interpreter.load_state(&saved).unwrap();
+        assert_eq!(run(&mut interpreter), expected);
+
+        assert_eq!(interpreter.load_state(&saved[..saved.len() - 1]), Err(SaveStateError::Truncated));
+        assert_eq!(interpreter.load_state(b"RUSTCHX"), Err(SaveStateError::NotASaveState));
+
+        let other = fixture::counter(ChipProfile::e0c6s48());
+        assert_eq!(interpreter.load_state(&other.save_state()), Err(SaveStateError::WrongChip { expected: "E0C6S46", found: "E0C6S48".to_string() }));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{cell::RefCell, collections::HashSet, fmt, rc::Rc};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+
+use crate::{input::Button, interpreter::Interpreter, primitive::u4};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    Parse(String),
+    Runtime(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Parse(message) => write!(f, "script doesn't parse: {}", message),
+            ScriptError::Runtime(message) => write!(f, "script failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(error: Box<EvalAltResult>) -> Self {
+        ScriptError::Runtime(error.to_string())
+    }
+}
+
+// What the script sees of the emulator while it runs, and what it asked to change
+#[derive(Default)]
+struct Context {
+    registers: Vec<(&'static str, i64)>,
+    memory: Vec<u4>,
+    cycles: i64,
+    writes: Vec<(usize, u4)>,
+    buttons: Vec<(Button, bool)>,
+    breakpoints: HashSet<usize>,
+}
+
+impl Context {
+    fn load(&mut self, interpreter: &Interpreter) {
+        let state = &interpreter.state;
+        let reg = state.registers;
+        self.registers = vec![
+            ("PC", state.pc() as i64),
+            ("PCS", reg.PCS.into()),
+            ("PCP", u8::from(reg.PCP).into()),
+            ("PCB", u8::from(reg.PCB).into()),
+            ("NPP", u8::from(reg.NPP).into()),
+            ("NBP", u8::from(reg.NBP).into()),
+            ("SP", reg.SP.into()),
+            ("X", u16::from(reg.X).into()),
+            ("Y", u16::from(reg.Y).into()),
+            ("RP", u8::from(reg.RP).into()),
+            ("A", u8::from(reg.A).into()),
+            ("B", u8::from(reg.B).into()),
+            ("F", state.flags.bits().into()),
+        ];
+        self.memory = state.memory.slice(0..4096);
+        self.cycles = state.cycles.into();
+    }
+
+    fn apply(&mut self, interpreter: &mut Interpreter) {
+        for (addr, value) in self.writes.drain(..) {
+            interpreter.state.memory.set(addr, value);
+        }
+    }
+
+    fn address(&self, addr: i64) -> Result<usize, Box<EvalAltResult>> {
+        usize::try_from(addr).ok().filter(|addr| *addr < self.memory.len())
+            .ok_or_else(|| format!("address {:#X} is out of the data memory", addr).into())
+    }
+}
+
+fn button(name: &str) -> Result<Button, Box<EvalAltResult>> {
+    match name {
+        "A" | "a" => Ok(Button::A),
+        "B" | "b" => Ok(Button::B),
+        "C" | "c" => Ok(Button::C),
+        _ => Err(format!("unknown button `{}`", name).into()),
+    }
+}
+
+/// A Rhai script run alongside the emulation, for automation and cheats.
+///
+/// The script's top level runs once on load. It can define `on_frame()`, called after every
+/// frame, and `on_breakpoint(pc)`, called before the instruction at a breakpoint executes.
+/// It sees the emulator through `reg(name)`, `read(addr)`, `write(addr, value)`, `press(button)`,
+/// `release(button)`, `breakpoint(pc)`, `clear_breakpoint(pc)` and `cycles()`. Its writes are
+/// applied once the call returns, its buttons wait in `take_buttons` for the caller to apply them
+/// like its own input. Rhai functions don't see the top level's variables, so the
+/// hooks keep their own state in `this`, a map that lasts between calls.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    this: Dynamic,
+    context: Rc<RefCell<Context>>,
+}
+
+impl Script {
+    pub fn load(source: &str, interpreter: &mut Interpreter) -> Result<Self, ScriptError> {
+        let context = Rc::new(RefCell::new(Context::default()));
+        let engine = engine(&context);
+        let ast = engine.compile(source).map_err(|error| ScriptError::Parse(error.to_string()))?;
+
+        let mut script = Self { engine, ast, scope: Scope::new(), this: Map::new().into(), context };
+        script.run(interpreter, |engine, scope, ast, _| engine.run_ast_with_scope(scope, ast))?;
+        Ok(script)
+    }
+
+    pub fn on_frame(&mut self, interpreter: &mut Interpreter) -> Result<(), ScriptError> {
+        self.call(interpreter, "on_frame", ())
+    }
+
+    /// The buttons pressed (`true`) and released since the last call, in order.
+    pub fn take_buttons(&mut self) -> Vec<(Button, bool)> {
+        std::mem::take(&mut self.context.borrow_mut().buttons)
+    }
+
+    pub fn is_breakpoint(&self, pc: usize) -> bool {
+        let context = self.context.borrow();
+        !context.breakpoints.is_empty() && context.breakpoints.contains(&pc)
+    }
+
+    /// Calls `on_breakpoint` with the current PC.
+    pub fn on_breakpoint(&mut self, interpreter: &mut Interpreter) -> Result<(), ScriptError> {
+        let pc = interpreter.pc() as i64;
+        self.call(interpreter, "on_breakpoint", (pc,))
+    }
+
+    // Calls the hook if the script defines it, without running the top level again
+    fn call(&mut self, interpreter: &mut Interpreter, name: &str, args: impl FuncArgs) -> Result<(), ScriptError> {
+        let mut values = vec![];
+        args.parse(&mut values);
+        if !self.ast.iter_functions().any(|function| function.name == name && function.params.len() == values.len()) {
+            return Ok(());
+        }
+
+        self.run(interpreter, |engine, scope, ast, this| {
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(this);
+            engine.call_fn_with_options::<Dynamic>(options, scope, ast, name, values).map(|_| ())
+        })
+    }
+
+    fn run(
+        &mut self,
+        interpreter: &mut Interpreter,
+        call: impl FnOnce(&Engine, &mut Scope<'static>, &AST, &mut Dynamic) -> Result<(), Box<EvalAltResult>>,
+    ) -> Result<(), ScriptError> {
+        self.context.borrow_mut().load(interpreter);
+        let result = call(&self.engine, &mut self.scope, &self.ast, &mut self.this);
+        // Changes made before an error are kept
+        self.context.borrow_mut().apply(interpreter);
+        result.map_err(ScriptError::from)
+    }
+}
+
+fn engine(context: &Rc<RefCell<Context>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.on_print(|text| log::info!("script: {}", text));
+
+    let shared = context.clone();
+    engine.register_fn("reg", move |name: &str| -> Result<i64, Box<EvalAltResult>> {
+        shared.borrow().registers.iter().find(|(register, _)| register.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+            .ok_or_else(|| format!("unknown register `{}`", name).into())
+    });
+
+    let shared = context.clone();
+    engine.register_fn("read", move |addr: i64| -> Result<i64, Box<EvalAltResult>> {
+        let context = shared.borrow();
+        Ok(u8::from(context.memory[context.address(addr)?]).into())
+    });
+
+    let shared = context.clone();
+    engine.register_fn("write", move |addr: i64, value: i64| -> Result<(), Box<EvalAltResult>> {
+        let mut context = shared.borrow_mut();
+        let addr = context.address(addr)?;
+        let value = u8::try_from(value).ok().and_then(|value| u4::try_from(value).ok())
+            .ok_or_else(|| format!("{} doesn't fit in 4 bits", value))?;
+        // Later reads see the write before it reaches the memory
+        context.memory[addr] = value;
+        context.writes.push((addr, value));
+        Ok(())
+    });
+
+    let shared = context.clone();
+    engine.register_fn("press", move |name: &str| -> Result<(), Box<EvalAltResult>> {
+        shared.borrow_mut().buttons.push((button(name)?, true));
+        Ok(())
+    });
+
+    let shared = context.clone();
+    engine.register_fn("release", move |name: &str| -> Result<(), Box<EvalAltResult>> {
+        shared.borrow_mut().buttons.push((button(name)?, false));
+        Ok(())
+    });
+
+    let shared = context.clone();
+    engine.register_fn("breakpoint", move |pc: i64| {
+        shared.borrow_mut().breakpoints.insert(pc as usize);
+    });
+
+    let shared = context.clone();
+    engine.register_fn("clear_breakpoint", move |pc: i64| {
+        shared.borrow_mut().breakpoints.remove(&(pc as usize));
+    });
+
+    let shared = context.clone();
+    engine.register_fn("cycles", move || shared.borrow().cycles);
+
+    engine
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{chip::ChipProfile, fixture};
+
+    #[test]
+    fn hooks() {
+        let mut interpreter = fixture::counter(ChipProfile::e0c6s46());
+
+        let mut script = Script::load(r#"
+            breakpoint(0x102);
+
+            fn on_breakpoint(pc) {
+                this.hits = (this.hits ?? 0) + 1;
+                write(0x200, reg("A"));
+                if this.hits == 3 { clear_breakpoint(pc); }
+            }
+
+            fn on_frame() {
+                press("A");
+                write(0x201, read(0x200) + 1);
+            }
+        "#, &mut interpreter).unwrap();
+
+        let mut hits = 0;
+        for _ in 0..100 {
+            if script.is_breakpoint(interpreter.pc()) {
+                hits += 1;
+                script.on_breakpoint(&mut interpreter).unwrap();
+            }
+            interpreter.step().unwrap();
+        }
+        assert_eq!(hits, 3);
+        assert_eq!(interpreter.state.memory.get(0x200), u4![5]);
+
+        script.on_frame(&mut interpreter).unwrap();
+        assert!(!interpreter.state.input.is_button_pressed(Button::A));
+        assert_eq!(script.take_buttons(), vec![(Button::A, true)]);
+        assert!(script.take_buttons().is_empty());
+        assert_eq!(interpreter.state.memory.get(0x201), u4![6]);
+
+        assert!(matches!(Script::load("write(0x201, 16)", &mut interpreter), Err(ScriptError::Runtime(_))));
+        assert!(matches!(Script::load("fn (", &mut interpreter), Err(ScriptError::Parse(_))));
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    rc::Rc,
+};
+
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    interrupt::Interrupt,
+    memory::{REG_PROG_TIMER_RELOAD_DATA_HI, REG_PROG_TIMER_RELOAD_DATA_LO, REG_SCTRG_SEN_SCS1_SCS0, REG_SD3_SD2_SD1_SD0, REG_SD7_SD6_SD5_SD4},
+    peripheral::Peripheral,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+// SCS1/SCS0 clock sources. 0b00 is slave mode, clocked by the other end.
+const SCS_PROG_TIMER: u4 = u4::new(0b01);
+const SCS_OSC1_HALF: u4 = u4::new(0b10);
+const SCS_OSC1: u4 = u4::new(0b11);
+const PROG_TIMER_CYCLES: u32 = 128;
+// How long the master waits for the slave's byte, which the other process may not have sent yet.
+// 100 ms of OSC1 cycles.
+const REPLY_TIMEOUT_CYCLES: u32 = 3277;
+
+/// Byte transport between the serial interfaces of two devices.
+///
+/// Transfers are modelled a byte at a time: the master sends its byte when the 8 clock pulses are done
+/// and waits for the byte the slave had loaded, while a slave sends its byte as soon as it is ready
+/// and completes when the master's byte arrives.
+pub trait SerialLink {
+    fn send(&mut self, byte: u8) -> io::Result<()>;
+    /// Takes the next byte if one arrived, without waiting.
+    fn receive(&mut self) -> Option<u8>;
+}
+
+/// In-process link, for wiring two `Interpreter`s stepped from the same thread.
+pub struct LocalLink {
+    tx: Rc<RefCell<VecDeque<u8>>>,
+    rx: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl LocalLink {
+    pub fn pair() -> (Self, Self) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+
+        (
+            Self { tx: a.clone(), rx: b.clone() },
+            Self { tx: b, rx: a },
+        )
+    }
+}
+
+impl SerialLink for LocalLink {
+    fn send(&mut self, byte: u8) -> io::Result<()> {
+        self.tx.borrow_mut().push_back(byte);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        self.rx.borrow_mut().pop_front()
+    }
+}
+
+/// Link over a TCP socket, for connecting two emulator processes.
+pub struct SocketLink {
+    stream: TcpStream,
+}
+
+impl SocketLink {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream })
+    }
+}
+
+impl SerialLink for SocketLink {
+    fn send(&mut self, byte: u8) -> io::Result<()> {
+        self.stream.write_all(&[byte])
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        let mut buf = [0u8; 1];
+        match self.stream.read(&mut buf) {
+            Ok(1) => Some(buf[0]),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+enum Transfer {
+    #[default]
+    Idle,
+    Master { remaining_cycles: u32 },
+    // The master's byte is out, waiting for the slave's
+    Reply { remaining_cycles: u32 },
+    Slave,
+}
+
+/// Serial interface. SEN is ignored: it only picks the SCLK edge the bits shift on, which a link
+/// carrying whole bytes can't tell apart.
+#[derive(Clone, Default)]
+pub struct Serial {
+    pub link: Option<Rc<RefCell<dyn SerialLink>>>,
+    transfer: Transfer,
+    pending: bool,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            link: None,
+            transfer: Transfer::Idle,
+            pending: false,
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.transfer != Transfer::Idle
+    }
+
+    /// Starts a transfer when SCTRG is written. `prog_timer_reload` is only used by the programmable timer clock source.
+    pub fn trigger(&mut self, clock_source: u4, data: u8, prog_timer_reload: u8) {
+        let bit_cycles = match clock_source {
+            SCS_OSC1 => 1,
+            SCS_OSC1_HALF => 2,
+            SCS_PROG_TIMER => u32::from(prog_timer_reload.max(1)) * PROG_TIMER_CYCLES * 2,
+            _ => {
+                self.send(data);
+                self.transfer = Transfer::Slave;
+                return;
+            }
+        };
+
+        self.transfer = Transfer::Master { remaining_cycles: 8 * bit_cycles };
+    }
+
+    /// Advances the shift clock, returning the received byte once a transfer completes.
+    pub fn clock(&mut self, delta_cycles: u32, data: u8) -> Option<u8> {
+        match self.transfer {
+            Transfer::Idle => None,
+            Transfer::Master { remaining_cycles } if remaining_cycles > delta_cycles => {
+                self.transfer = Transfer::Master { remaining_cycles: remaining_cycles - delta_cycles };
+                None
+            }
+            Transfer::Master { remaining_cycles } => {
+                self.send(data);
+                self.transfer = Transfer::Reply { remaining_cycles: REPLY_TIMEOUT_CYCLES };
+                self.clock(delta_cycles - remaining_cycles, data)
+            }
+            Transfer::Reply { remaining_cycles } => {
+                let received = self.receive();
+                if received.is_some() || self.link.is_none() || remaining_cycles <= delta_cycles {
+                    self.transfer = Transfer::Idle;
+                    // SIN idles high when nothing answers
+                    return Some(received.unwrap_or(0xFF));
+                }
+                self.transfer = Transfer::Reply { remaining_cycles: remaining_cycles - delta_cycles };
+                None
+            }
+            Transfer::Slave => {
+                let received = self.receive();
+                if received.is_some() {
+                    self.transfer = Transfer::Idle;
+                }
+                received
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        self.link.as_ref().and_then(|link| link.borrow_mut().receive())
+    }
+
+    // A link that fails is dropped, as if the cable was pulled
+    fn send(&mut self, data: u8) {
+        let result = self.link.as_ref().map(|link| link.borrow_mut().send(data));
+        if let Some(Err(error)) = result {
+            log::warn!("serial link failed: {}, disconnecting it", error);
+            self.link = None;
+        }
+    }
+}
+
+impl Peripheral for Serial {
+    fn handles(&self, addr: usize) -> bool {
+        matches!(addr, REG_SD3_SD2_SD1_SD0 | REG_SD7_SD6_SD5_SD4 | REG_SCTRG_SEN_SCS1_SCS0)
+    }
+
+    fn write(&mut self, addr: usize, value: u4, registers: &mut [u4]) -> Result<(), EmulationError> {
+        if addr == REG_SCTRG_SEN_SCS1_SCS0 && value.is_set(u4![0b1000]) {
+            registers[addr] = value & !u4![0b1000];
+            let data = u8::from_be_nibbles(vec![registers[REG_SD7_SD6_SD5_SD4], registers[REG_SD3_SD2_SD1_SD0]]);
+            let reload = u8::from_be_nibbles(vec![registers[REG_PROG_TIMER_RELOAD_DATA_HI], registers[REG_PROG_TIMER_RELOAD_DATA_LO]]);
+            self.trigger(value & u4![0b0011], data, reload);
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, delta_cycles: u32, registers: &mut [u4]) {
+        let data = u8::from_be_nibbles(vec![registers[REG_SD7_SD6_SD5_SD4], registers[REG_SD3_SD2_SD1_SD0]]);
+        if let Some(received) = self.clock(delta_cycles, data) {
+            registers[REG_SD3_SD2_SD1_SD0] = received.nibble(0);
+            registers[REG_SD7_SD6_SD5_SD4] = received.nibble(1);
+            self.pending = true;
+        }
+    }
+
+    fn interrupt_request(&mut self) -> Option<(Interrupt, u4)> {
+        std::mem::take(&mut self.pending).then_some((Interrupt::Serial, u4![0b0001]))
+    }
+
+    fn reset(&mut self) {
+        self.transfer = Transfer::Idle;
+        self.pending = false;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        match self.transfer {
+            Transfer::Idle => writer.u8(0),
+            Transfer::Master { remaining_cycles } => {
+                writer.u8(1);
+                writer.u32(remaining_cycles);
+            },
+            Transfer::Slave => writer.u8(2),
+            Transfer::Reply { remaining_cycles } => {
+                writer.u8(3);
+                writer.u32(remaining_cycles);
+            },
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.transfer = match reader.u8()? {
+            0 => Transfer::Idle,
+            1 => Transfer::Master { remaining_cycles: reader.u32()? },
+            2 => Transfer::Slave,
+            3 => Transfer::Reply { remaining_cycles: reader.u32()? },
+            _ => return Err(SaveStateError::Corrupt("serial transfer")),
+        };
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory;
+    use crate::state::State;
+
+    fn linked(link: LocalLink) -> State {
+        let mut state = State::new();
+        state.memory.bus.get_mut::<Serial>().unwrap().link = Some(Rc::new(RefCell::new(link)));
+        state.memory.set(memory::REG_EISIO, u4![0b0001]);
+        state.flags.set(Flags::I, true);
+        state
+    }
+
+    fn load(state: &mut State, data: u8) {
+        state.memory.set(memory::REG_SD3_SD2_SD1_SD0, data.nibble(0));
+        state.memory.set(memory::REG_SD7_SD6_SD5_SD4, data.nibble(1));
+    }
+
+    fn data(state: &State) -> u8 {
+        u8::from_be_nibbles(vec![
+            state.memory.get(memory::REG_SD7_SD6_SD5_SD4),
+            state.memory.get(memory::REG_SD3_SD2_SD1_SD0),
+        ])
+    }
+
+    #[test]
+    fn master_slave_exchange() {
+        let (a, b) = LocalLink::pair();
+        let mut master = linked(a);
+        let mut slave = linked(b);
+
+        load(&mut slave, 0x5A);
+        slave.memory.set(memory::REG_SCTRG_SEN_SCS1_SCS0, u4![0b1000]);
+
+        load(&mut master, 0xC3);
+        master.memory.set(memory::REG_SCTRG_SEN_SCS1_SCS0, u4![0b1011]);
+
+        master.update_timers(7);
+        assert_eq!(master.check_interrupts(), None);
+        master.update_timers(1);
+        assert_eq!(master.check_interrupts(), Some(Interrupt::Serial));
+        assert_eq!(data(&master), 0x5A);
+
+        slave.update_timers(5);
+        assert_eq!(slave.check_interrupts(), Some(Interrupt::Serial));
+        assert_eq!(data(&slave), 0xC3);
+    }
+
+    #[test]
+    fn master_waits_for_reply() {
+        let (a, mut b) = LocalLink::pair();
+        let mut master = linked(a);
+
+        load(&mut master, 0xC3);
+        master.memory.set(memory::REG_SCTRG_SEN_SCS1_SCS0, u4![0b1011]);
+        master.update_timers(100);
+        assert_eq!(master.check_interrupts(), None);
+        assert_eq!(b.receive(), Some(0xC3));
+
+        b.send(0x5A).unwrap();
+        master.update_timers(1);
+        assert_eq!(master.check_interrupts(), Some(Interrupt::Serial));
+        assert_eq!(data(&master), 0x5A);
+
+        master.memory.get(memory::REG_SERIAL_INTERRUPT_FACTOR_FLAGS);
+        master.memory.set(memory::REG_SCTRG_SEN_SCS1_SCS0, u4![0b1011]);
+        master.update_timers(8 + REPLY_TIMEOUT_CYCLES - 1);
+        assert_eq!(master.check_interrupts(), None);
+        master.update_timers(1);
+        assert_eq!(master.check_interrupts(), Some(Interrupt::Serial));
+        assert_eq!(data(&master), 0xFF);
+    }
+
+    #[test]
+    fn socket_polls() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut a = SocketLink::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap()).unwrap();
+        let mut b = SocketLink::new(listener.accept().unwrap().0).unwrap();
+
+        assert_eq!(b.receive(), None);
+        a.send(0x5A).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(1);
+        let received = std::iter::repeat_with(|| b.receive()).find(|byte| byte.is_some() || std::time::Instant::now() > deadline);
+        assert_eq!(received, Some(Some(0x5A)));
+        assert_eq!(b.receive(), None);
+    }
+}
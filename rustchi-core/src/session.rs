@@ -0,0 +1,180 @@
+use std::{fmt, time::Duration};
+
+use crate::{
+    chip::ChipProfile,
+    error::EmulationError,
+    frame::Frame,
+    input::Button,
+    interpreter::Interpreter,
+    movie::Elapsed,
+    primitive::u4,
+    rom::RomError,
+    osc::OSC1_CLOCK,
+};
+
+// Frames are taken at the same rate as the terminal so that LCD persistence behaves the same.
+const FRAME_RATE: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionError {
+    Emulation(EmulationError),
+    /// `wait_until` gave up after this many emulated seconds.
+    Timeout { seconds: f64 },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Emulation(error) => write!(f, "{}", error),
+            SessionError::Timeout { seconds } => write!(f, "condition not met after {} emulated seconds", seconds),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<EmulationError> for SessionError {
+    fn from(error: EmulationError) -> Self {
+        SessionError::Emulation(error)
+    }
+}
+
+/// The emulator at some point of a session.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// OSC1 cycles since the session started.
+    pub cycles: u64,
+    /// The LCD at the last frame.
+    pub frame: Frame,
+    /// The whole data memory, RAM, display RAM and I/O, by address.
+    pub memory: Vec<u4>,
+}
+
+/// Drives an interpreter in emulated time, for tests and bots.
+pub struct Session {
+    pub interpreter: Interpreter,
+    frame: Frame,
+    elapsed: Elapsed,
+    cycles: u64,
+}
+
+impl Session {
+    pub fn new(mut interpreter: Interpreter) -> Self {
+        let frame = interpreter.frame();
+        let elapsed = Elapsed::new(&interpreter);
+        Self { interpreter, frame, elapsed, cycles: 0 }
+    }
+
+    pub fn load(bytes: &[u8], profile: ChipProfile) -> Result<Self, RomError> {
+        Interpreter::load(bytes, profile).map(Self::new)
+    }
+
+    /// Holds the button down for `duration` of emulated time.
+    pub fn press(&mut self, button: Button, duration: Duration) -> Result<(), SessionError> {
+        self.interpreter.press_button(button);
+        self.wait(duration)?;
+        self.interpreter.release_button(button);
+        Ok(())
+    }
+
+    pub fn wait(&mut self, duration: Duration) -> Result<(), SessionError> {
+        self.wait_cycles(osc1_cycles(duration))
+    }
+
+    /// Runs for this many OSC1 cycles.
+    pub fn wait_cycles(&mut self, cycles: u64) -> Result<(), SessionError> {
+        self.run(self.cycles + cycles, |_| false)?;
+        Ok(())
+    }
+
+    pub fn wait_seconds(&mut self, seconds: f64) -> Result<(), SessionError> {
+        self.wait(Duration::from_secs_f64(seconds))
+    }
+
+    /// Runs until `predicate` holds, checking it every frame, for at most `timeout` of emulated time.
+    pub fn wait_until(&mut self, timeout: Duration, mut predicate: impl FnMut(&Snapshot) -> bool) -> Result<Snapshot, SessionError> {
+        let snapshot = self.snapshot();
+        if predicate(&snapshot) {
+            return Ok(snapshot);
+        }
+
+        self.run(self.cycles + osc1_cycles(timeout), &mut predicate)?
+            .ok_or(SessionError::Timeout { seconds: timeout.as_secs_f64() })
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cycles: self.cycles,
+            frame: self.frame.clone(),
+            memory: self.interpreter.state.memory.slice(0..4096),
+        }
+    }
+
+    /// OSC1 cycles since the session started.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The LCD at the last frame.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    // Steps until `target` cycles since the start, taking a frame every 1/30 s of emulated time
+    // and stopping early with the snapshot `on_frame` accepts.
+    fn run(&mut self, target: u64, mut on_frame: impl FnMut(&Snapshot) -> bool) -> Result<Option<Snapshot>, EmulationError> {
+        while self.cycles < target {
+            self.interpreter.step()?;
+            self.cycles = self.elapsed.update(&self.interpreter);
+
+            if self.interpreter.cycle_counter >= u64::from(self.interpreter.state.clock_speed) / FRAME_RATE {
+                self.interpreter.reset_cycle_counter();
+                self.frame = self.interpreter.frame();
+
+                let snapshot = self.snapshot();
+                if on_frame(&snapshot) {
+                    return Ok(Some(snapshot));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn osc1_cycles(duration: Duration) -> u64 {
+    (duration.as_secs_f64() * f64::from(OSC1_CLOCK)).round() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    fn drive() {
+        let mut session = Session::new(fixture::counter(ChipProfile::e0c6s46()));
+
+        session.wait_seconds(0.5).unwrap();
+        assert!((16_384..16_400).contains(&session.cycles()));
+
+        session.press(Button::A, Duration::from_millis(100)).unwrap();
+        assert!(!session.interpreter.state.input.is_button_pressed(Button::A));
+
+        let start = session.cycles();
+        session.interpreter.press_button(Button::B);
+        let snapshot = session.wait_until(Duration::from_secs(1), |snapshot| snapshot.cycles - start > 5_000).unwrap();
+        assert!(snapshot.cycles - start < 5_000 + 32_768 / 30 + 10);
+        assert_eq!(snapshot.memory[0xF40], u4![0b1101]);
+
+        let error = session.wait_until(Duration::from_millis(100), |_| false).unwrap_err();
+        assert_eq!(error, SessionError::Timeout { seconds: 0.1 });
+
+        // State::cycles wraps, the session's count doesn't
+        session.interpreter.state.cycles = u32::MAX - 100;
+        session = Session::new(session.interpreter);
+        session.wait_seconds(0.1).unwrap();
+        assert!(session.interpreter.state.cycles < 4_000);
+        assert!((3_277..3_300).contains(&session.cycles()));
+    }
+}
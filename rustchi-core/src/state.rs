@@ -2,40 +2,121 @@ use crate::prelude::*;
 
 use crate::{
     change::{self, Change, Changes, Register},
+    chip::ChipProfile,
+    savestate::{SaveStateError, StateReader, StateWriter},
     registers::*,
-    memory::{self, Memory},
+    memory::Memory,
     input::Input,
+    interrupt::{Interrupt, INTERRUPT_CYCLES},
+    osc::{Oscillator, OSC1_CLOCK},
+    watchdog::Watchdog,
 };
 
-const TIMER_1HZ_CYCLES: u32 = 32768;
-const TIMER_256HZ_CYCLES: u32 = 128;
 
 #[derive(Clone)]
 pub struct State {
     pub tick: u32,
+    // CPU clock in Hz, OSC1 or OSC3 depending on CLKCHG.
     pub clock_speed: u32,
+    // Elapsed OSC1 cycles. The timers always run on OSC1, whatever clock the CPU is using.
+    // Wraps around after about 36 hours.
     pub cycles: u32,
+    osc1_remainder: u64,
     pub flags: Flags,
     pub registers: Registers,
     pub memory: Memory,
     pub changes: Changes,
     pub input: Input,
+    pub halted: bool,
 }
 
 impl State {
     pub fn new() -> Self {
+        Self::with_profile(ChipProfile::e0c6s46())
+    }
+
+    pub fn with_profile(profile: ChipProfile) -> Self {
         Self {
             tick: 1,
-            clock_speed: 32_768,
+            clock_speed: OSC1_CLOCK,
             cycles: 0,
+            osc1_remainder: 0,
             flags: Flags::empty(),
             registers: Registers::zero(),
-            memory: Memory::new(),
+            memory: Memory::with_profile(profile),
             changes: Changes::new(),
             input: Input::all_high(),
+            halted: false,
         }
     }
 
+    /// Initial reset. Inputs and the peripherals' configuration, like the battery, the serial link
+    /// or the watchdog, survive it, and cycles keep counting.
+    pub fn reset(&mut self) {
+        let mut state = Self::with_profile(self.memory.profile.clone());
+        state.tick = self.tick;
+        state.cycles = self.cycles;
+        state.memory.bus = std::mem::take(&mut self.memory.bus);
+        state.memory.bus.reset();
+        state.memory.diagnostics = self.memory.diagnostics.clone();
+        state.set_input(self.input.clone());
+
+        *self = state;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.tick);
+        writer.u32(self.clock_speed);
+        writer.u32(self.cycles);
+        writer.u64(self.osc1_remainder);
+        writer.u8(self.flags.bits());
+
+        let registers = &self.registers;
+        writer.u8(registers.PCS);
+        writer.u4(registers.PCP);
+        writer.u1(registers.PCB);
+        writer.u4(registers.NPP);
+        writer.u1(registers.NBP);
+        writer.u8(registers.SP);
+        writer.u12(registers.X);
+        writer.u12(registers.Y);
+        writer.u4(registers.RP);
+        writer.u4(registers.A);
+        writer.u4(registers.B);
+
+        writer.u4(self.input.state);
+        writer.u4(self.input.k1_state);
+        writer.bool(self.halted);
+        self.memory.save_state(writer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.tick = reader.u32()?;
+        self.clock_speed = reader.u32()?;
+        self.cycles = reader.u32()?;
+        self.osc1_remainder = reader.u64()?;
+        self.flags = Flags::from_bits(reader.u8()?).ok_or(SaveStateError::Corrupt("flags"))?;
+
+        self.registers = Registers {
+            PCS: reader.u8()?,
+            PCP: reader.u4("PCP")?,
+            PCB: reader.u1("PCB")?,
+            NPP: reader.u4("NPP")?,
+            NBP: reader.u1("NBP")?,
+            SP: reader.u8()?,
+            X: reader.u12("X")?,
+            Y: reader.u12("Y")?,
+            RP: reader.u4("RP")?,
+            A: reader.u4("A")?,
+            B: reader.u4("B")?,
+        };
+
+        self.input = Input { state: reader.u4("input")?, k1_state: reader.u4("input")? };
+        self.halted = reader.bool("halted")?;
+        self.changes = Changes::new();
+        self.memory.load_state(reader)
+    }
+
     pub fn pc(&self) -> usize {
         let step: usize = self.registers.PCS.into();
         let page: usize = self.registers.PCP.into();
@@ -153,89 +234,57 @@ impl State {
         state
     }
 
-    fn timer_data(&self) -> u8 {
-        let bytes = self.memory.bytes.borrow();
-        u8![0]
-            .with_nibble(0, bytes[memory::REG_PROG_TIMER_DATA_LO])
-            .with_nibble(1, bytes[memory::REG_PROG_TIMER_DATA_HI])
+    pub fn set_input(&mut self, input: Input) {
+        self.input = input;
+        self.memory.set_input(&self.input);
     }
 
-    pub fn update_timers(&mut self, delta_cycles: u32) {
-        self.memory.clock_timer_ticks += delta_cycles;
-        let timer_data = self.timer_data();
-
-        {
-            let mut bytes = self.memory.bytes.borrow_mut();
-
-            if bytes[memory::REG_PROG_TIMER_RESET_ENABLE].is_set(u4![0b0001]) {
-                if self.memory.prog_timer_ticks >= TIMER_256HZ_CYCLES {
-                    self.memory.prog_timer_ticks -= TIMER_256HZ_CYCLES;
-                    let timer_data = timer_data - 1;
-                    bytes[memory::REG_PROG_TIMER_DATA_LO] = timer_data.nibble(0);
-                    bytes[memory::REG_PROG_TIMER_DATA_HI] = timer_data.nibble(1);
-                }
+    /// Switches the CPU between OSC1 and OSC3, following the oscillator on the bus.
+    pub fn update_clock(&mut self) {
+        self.clock_speed = self.memory.bus.get::<Oscillator>().map_or(OSC1_CLOCK, Oscillator::cpu_clock);
+    }
 
-                self.memory.prog_timer_ticks += delta_cycles;
-            }
+    /// Converts CPU clock cycles into OSC1 cycles, carrying over the fraction.
+    pub fn osc1_cycles(&mut self, cpu_cycles: u32) -> u32 {
+        if self.clock_speed == OSC1_CLOCK {
+            return cpu_cycles;
         }
 
-        // println!("prog_timer_data {}", self.timer_data());
-        // println!("prog_timer_ticks {}", self.memory.prog_timer_ticks);
+        self.osc1_remainder += u64::from(cpu_cycles) * u64::from(OSC1_CLOCK);
+        let cycles = self.osc1_remainder / u64::from(self.clock_speed);
+        self.osc1_remainder %= u64::from(self.clock_speed);
+        cycles.try_into().unwrap()
     }
 
-    pub fn check_interrupts(&mut self) -> Option<u8> {
-        let timer_data = self.timer_data();
-        let mut bytes = self.memory.bytes.borrow_mut();
-
-        // Interrupt vector (PCP and PCS), low to high priority
-        // 0x102 Clock timer
-        // 0x104 Stopwatch timer
-        // 0x106 Input (K00–K03)
-        // 0x108 Input (K10–K13)
-        // 0x10A Serial interface
-        // 0x10C Programmable timer
-
-        if bytes[memory::REG_PROG_TIMER_RESET_ENABLE].is_set(u4![0b0001]) && timer_data == 0 {
-            self.memory.prog_timer_ticks += 12;
-            bytes[memory::REG_PROG_TIMER_DATA_LO] = bytes[memory::REG_PROG_TIMER_RELOAD_DATA_LO];
-            bytes[memory::REG_PROG_TIMER_DATA_HI] = bytes[memory::REG_PROG_TIMER_RELOAD_DATA_HI];
-
-            bytes[memory::REG_PROGRAMMABLE_TIMER_INTERRUPT_FACTOR_FLAGS] = bytes[memory::REG_PROGRAMMABLE_TIMER_INTERRUPT_FACTOR_FLAGS] | u4![0b0001];
-
-            if bytes[memory::REG_EIPT].is_set(u4![0b0001]) {
-                return Some(u8![0x0C]);
-            } else {
-                return None;
-            }
-        }
-        
-        // 1hz clock timer. It's the only one used by the P1.
-        if bytes[memory::REG_EIT1_EIT2_EIT8_EIT32].is_set(u4![0b1000]) && self.memory.clock_timer_ticks >= TIMER_1HZ_CYCLES {
-            self.memory.clock_timer_ticks = 12;
-            bytes[memory::REG_CLOCK_INTERRUPT_FACTOR_FLAGS] = bytes[memory::REG_CLOCK_INTERRUPT_FACTOR_FLAGS] | u4![0b1000];
+    pub fn update_timers(&mut self, delta_cycles: u32) {
+        self.memory.tick(delta_cycles);
 
-            return Some(u8![0x02]);
+        if self.memory.bus.get_mut::<Watchdog>().is_some_and(|watchdog| watchdog.take_reset()) {
+            self.reset();
         }
-
-        return None;
     }
 
-    pub fn process_interrupts(&mut self, pcs: u8) -> u64 {
-        if !self.flags.contains(Flags::I) {
-            return 0;
-        }
-
-        let mut bytes = self.memory.bytes.borrow_mut();
+    pub fn check_interrupts(&self) -> Option<Interrupt> {
+        let bytes = self.memory.bytes.borrow();
+        self.memory.interrupts.pending(self.flags.contains(Flags::I), &bytes[..])
+    }
 
+    /// Pushes the PC like CALL does and jumps to the interrupt vector on page 1.
+    pub fn take_interrupt(&mut self, interrupt: Interrupt) -> u32 {
+        self.memory.interrupts.acknowledge(interrupt);
         self.flags.set(Flags::I, false);
-        bytes[usize::from(self.registers.SP - 1)] = self.registers.PCP;
-        bytes[usize::from(self.registers.SP - 2)] = self.registers.PCS.nibble(1);
-        bytes[usize::from(self.registers.SP - 3)] = self.registers.PCS.nibble(2);
-        self.registers.SP -= 3;
+
+        let sp = self.registers.SP;
+        self.memory.set(sp.wrapping_sub(1).into(), self.registers.PCP);
+        self.memory.set(sp.wrapping_sub(2).into(), self.registers.PCS.nibble(1));
+        self.memory.set(sp.wrapping_sub(3).into(), self.registers.PCS.nibble(0));
+        self.registers.SP = sp.wrapping_sub(3);
         self.registers.NPP = u4![0x1];
         self.registers.PCP = u4![0x1];
-        self.registers.PCS = pcs;
-        return 12;
+        self.registers.PCS = interrupt.vector();
+        self.halted = false;
+
+        INTERRUPT_CYCLES
     }
 }
 
@@ -403,3 +452,64 @@ impl SetIdent<IdentU8, u8> for State {
         self
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{input::Button, memory, osc::OSC3_CLOCK};
+
+    #[test]
+    fn input_interrupts() {
+        let mut state = State::new();
+        state.flags.set(Flags::I, true);
+        state.memory.set(memory::REG_EIK03_EIK02_EIK01_EIK00, u4![0b0111]);
+
+        state.set_input(state.input.with_button_pressed(Button::A));
+        assert_eq!(state.check_interrupts(), Some(Interrupt::K0));
+        assert_eq!(state.memory.get(memory::REG_K00_K03_INTERRUPT_FACTOR_FLAGS), u4![0b0001]);
+        assert_eq!(state.check_interrupts(), None);
+
+        state.set_input(state.input.with_button_released(Button::A));
+        assert_eq!(state.check_interrupts(), None);
+
+        state.memory.set(memory::REG_DFK03_DFK02_DFK01_DFK00, u4![0b1011]);
+        state.set_input(state.input.with_button_pressed(Button::A));
+        assert_eq!(state.check_interrupts(), None);
+        state.set_input(state.input.with_button_released(Button::A));
+        assert_eq!(state.check_interrupts(), Some(Interrupt::K0));
+    }
+
+    #[test]
+    fn watchdog_nmi() {
+        let mut state = State::new();
+        state.memory.bus.get_mut::<Watchdog>().unwrap().enabled = true;
+
+        state.update_timers(4 * 32768 - 1);
+        state.memory.set(memory::REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET, u4![0b0001]);
+        state.update_timers(1);
+        assert_eq!(state.check_interrupts(), None);
+
+        state.halted = true;
+        state.update_timers(4 * 32768);
+        assert_eq!(state.check_interrupts(), Some(Interrupt::Watchdog));
+        assert_eq!(state.take_interrupt(Interrupt::Watchdog), 12);
+        assert_eq!(state.pc(), 0x100);
+        assert!(!state.halted);
+        assert_eq!(state.check_interrupts(), None);
+    }
+
+    #[test]
+    fn osc3_clock() {
+        let mut state = State::new();
+        state.memory.set(memory::REG_CLKCHG_OSCC_VSC1_VSC0, u4![0b1000]);
+        state.update_clock();
+        assert_eq!(state.clock_speed, OSC1_CLOCK);
+
+        state.memory.set(memory::REG_CLKCHG_OSCC_VSC1_VSC0, u4![0b1100]);
+        state.update_clock();
+        assert_eq!(state.clock_speed, OSC3_CLOCK);
+
+        let osc1_cycles: u32 = (0..OSC3_CLOCK / 5).map(|_| state.osc1_cycles(5)).sum();
+        assert_eq!(osc1_cycles, OSC1_CLOCK);
+    }
+}
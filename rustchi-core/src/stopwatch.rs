@@ -0,0 +1,122 @@
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    interrupt::Interrupt,
+    memory::{REG_SWH3_SWH2_SWH1_SWH0, REG_SWL3_SWL2_SWL1_SWL0, REG_SWRST_SWRUN},
+    peripheral::Peripheral,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const CYCLES_PER_SECOND: u64 = 32768;
+
+/// Stopwatch counting hundredths of a second in BCD, raising its factors every 1/10 s and every second.
+///
+/// The chip derives its 100 Hz from the 256 Hz clock with uneven steps, this one counts exact
+/// hundredths of OSC1.
+#[derive(Clone, Default)]
+pub struct Stopwatch {
+    // Hundredths of a second, 0 to 99
+    count: u8,
+    // OSC1 cycles towards the next hundredth, times 100
+    ticks: u64,
+    pending: Option<u4>,
+}
+
+impl Stopwatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Peripheral for Stopwatch {
+    fn handles(&self, addr: usize) -> bool {
+        matches!(addr, REG_SWL3_SWL2_SWL1_SWL0 | REG_SWH3_SWH2_SWH1_SWH0 | REG_SWRST_SWRUN)
+    }
+
+    fn read(&self, addr: usize, registers: &[u4]) -> Result<u4, EmulationError> {
+        Ok(match addr {
+            REG_SWL3_SWL2_SWL1_SWL0 => u4![self.count % 10],
+            REG_SWH3_SWH2_SWH1_SWH0 => u4![self.count / 10],
+            _ => registers[addr],
+        })
+    }
+
+    fn write(&mut self, addr: usize, value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        if addr == REG_SWRST_SWRUN && value.is_set(u4![0b0010]) {
+            self.count = 0;
+            self.ticks = 0;
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, delta_cycles: u32, registers: &mut [u4]) {
+        if !registers[REG_SWRST_SWRUN].is_set(u4![0b0001]) {
+            return;
+        }
+
+        let mut factor = u4![0];
+        self.ticks += u64::from(delta_cycles) * 100;
+        while self.ticks >= CYCLES_PER_SECOND {
+            self.ticks -= CYCLES_PER_SECOND;
+            self.count = (self.count + 1) % 100;
+            if self.count.is_multiple_of(10) {
+                factor = factor | u4![0b0001];
+            }
+            if self.count == 0 {
+                factor = factor | u4![0b0010];
+            }
+        }
+
+        if factor != u4![0] {
+            self.pending = Some(self.pending.unwrap_or(u4![0]) | factor);
+        }
+    }
+
+    fn interrupt_request(&mut self) -> Option<(Interrupt, u4)> {
+        self.pending.take().map(|factor| (Interrupt::Stopwatch, factor))
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u8(self.count);
+        writer.u64(self.ticks);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.count = reader.u8()?;
+        self.ticks = reader.u64()?;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{Memory, REG_STOPWATCH_INTERRUPT_FACTOR_FLAGS};
+
+    #[test]
+    fn counts_hundredths() {
+        let mut memory = Memory::new();
+        memory.tick(32768);
+        assert_eq!(memory.get(REG_SWL3_SWL2_SWL1_SWL0), u4![0]);
+
+        memory.set(REG_SWRST_SWRUN, u4![0b0001]);
+        memory.tick(3277);
+        assert_eq!((memory.get(REG_SWH3_SWH2_SWH1_SWH0), memory.get(REG_SWL3_SWL2_SWL1_SWL0)), (u4![1], u4![0]));
+        assert_eq!(memory.get(REG_STOPWATCH_INTERRUPT_FACTOR_FLAGS), u4![0b0001]);
+
+        memory.tick(32768 - 3277);
+        assert_eq!(memory.get(REG_STOPWATCH_INTERRUPT_FACTOR_FLAGS), u4![0b0011]);
+
+        memory.set(REG_SWRST_SWRUN, u4![0b0011]);
+        assert_eq!(memory.get(REG_SWRST_SWRUN), u4![0b0001]);
+        assert_eq!(memory.get(REG_SWH3_SWH2_SWH1_SWH0), u4![0]);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    memory::REG_SVDDT_SVDON_SVC1_SVC0,
+    peripheral::Peripheral,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const CYCLES_PER_HOUR: f64 = 32768.0 * 3600.0;
+
+// SVC1/SVC0 criteria voltages of the 3 V E0C6S46.
+const CRITERIA_VOLTAGES: [f64; 4] = [2.2, 2.5, 2.6, 2.7];
+
+/// Battery feeding the supply voltage detection circuit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    pub voltage: f64,
+    pub drain_per_hour: f64,
+}
+
+impl Battery {
+    /// A fresh pair of LR44 cells, not draining.
+    pub fn new() -> Self {
+        Self {
+            voltage: 3.0,
+            drain_per_hour: 0.0,
+        }
+    }
+
+    pub fn tick(&mut self, delta_cycles: u32) {
+        if self.drain_per_hour > 0.0 {
+            self.voltage = (self.voltage - self.drain_per_hour * f64::from(delta_cycles) / CYCLES_PER_HOUR).max(0.0);
+        }
+    }
+}
+
+impl Default for Battery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Supply voltage detection circuit.
+///
+/// While SVDON is set, SVDDT follows the battery. Turning SVDON off latches the last evaluation.
+#[derive(Clone)]
+pub struct Svd {
+    pub battery: Battery,
+    low: bool,
+    // Last value written, to catch SVDON turning off
+    control: u4,
+}
+
+impl Svd {
+    pub fn new() -> Self {
+        Self {
+            battery: Battery::new(),
+            low: false,
+            control: u4![0],
+        }
+    }
+
+    fn is_low(&self, control: u4) -> bool {
+        let criteria: usize = (control & u4![0b0011]).into();
+        self.battery.voltage < CRITERIA_VOLTAGES[criteria]
+    }
+
+    /// Called when SVDON/SVC1/SVC0 are written. Latches SVDDT when SVDON goes from 1 to 0,
+    /// with the criteria the detection ran with.
+    pub fn write(&mut self, control: u4) {
+        if self.control.is_set(u4![0b0100]) && !control.is_set(u4![0b0100]) {
+            self.low = self.is_low(self.control);
+        }
+        self.control = control;
+    }
+
+    /// Value of the register as seen by the ROM, with SVDDT filled in.
+    pub fn read(&self, control: u4) -> u4 {
+        let low = if control.is_set(u4![0b0100]) { self.is_low(control) } else { self.low };
+        (control & !u4![0b1000]) | if low { u4![0b1000] } else { u4![0] }
+    }
+}
+
+impl Default for Svd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Peripheral for Svd {
+    fn handles(&self, addr: usize) -> bool {
+        addr == REG_SVDDT_SVDON_SVC1_SVC0
+    }
+
+    fn read(&self, addr: usize, registers: &[u4]) -> Result<u4, EmulationError> {
+        Ok(Svd::read(self, registers[addr]))
+    }
+
+    fn write(&mut self, _addr: usize, value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        Svd::write(self, value);
+        Ok(())
+    }
+
+    fn tick(&mut self, delta_cycles: u32, _registers: &mut [u4]) {
+        self.battery.tick(delta_cycles);
+    }
+
+    fn reset(&mut self) {
+        self.low = false;
+        self.control = u4![0];
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.low);
+        writer.u4(self.control);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.low = reader.bool("SVD evaluation")?;
+        self.control = reader.u4("SVD control")?;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn svddt() {
+        let mut svd = Svd::new();
+        svd.battery.voltage = 2.55;
+
+        assert_eq!(svd.read(u4![0b0101]), u4![0b0101]);
+        assert_eq!(svd.read(u4![0b0110]), u4![0b1110]);
+
+        // Only turning SVDON off latches, with the criteria it ran with
+        svd.write(u4![0b0010]);
+        assert_eq!(svd.read(u4![0b0010]), u4![0b0010]);
+        svd.write(u4![0b0110]);
+        svd.write(u4![0b0001]);
+        svd.battery.voltage = 3.0;
+        assert_eq!(svd.read(u4![0b0001]), u4![0b1001]);
+        svd.write(u4![0b0000]);
+        assert_eq!(svd.read(u4![0b0000]), u4![0b1000]);
+        assert_eq!(svd.read(u4![0b0110]), u4![0b0110]);
+    }
+
+    #[test]
+    fn drain() {
+        let mut battery = Battery { voltage: 3.0, drain_per_hour: 0.5 };
+        battery.tick(32768 * 3600);
+        assert!((battery.voltage - 2.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    interrupt::Interrupt,
+    memory::{
+        REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET,
+        REG_PROG_TIMER_DATA_HI,
+        REG_PROG_TIMER_DATA_LO,
+        REG_PROG_TIMER_RELOAD_DATA_HI,
+        REG_PROG_TIMER_RELOAD_DATA_LO,
+        REG_PROG_TIMER_RESET_ENABLE,
+        REG_PTCOUT_PTC2_PTC1_PTC0,
+        REG_TM3_TM2_TM1_TM0,
+        REG_TM7_TM6_TM5_TM4,
+    },
+    peripheral::Peripheral,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+const TIMER_1HZ_CYCLES: u32 = 32768;
+const TIMER_2HZ_CYCLES: u32 = 16384;
+const TIMER_8HZ_CYCLES: u32 = 4096;
+const TIMER_32HZ_CYCLES: u32 = 1024;
+const TIMER_256HZ_CYCLES: u32 = 128;
+
+/// Free running 256 Hz counter, raising its factors on every 32 Hz, 8 Hz, 2 Hz and 1 Hz period.
+#[derive(Clone, Default)]
+pub struct ClockTimer {
+    // Cycles within the current second
+    ticks: u32,
+    pending: Option<u4>,
+}
+
+impl ClockTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+
+    fn counter(&self) -> u8 {
+        (self.ticks / TIMER_256HZ_CYCLES) as u8
+    }
+}
+
+impl Peripheral for ClockTimer {
+    fn handles(&self, addr: usize) -> bool {
+        matches!(addr, REG_TM3_TM2_TM1_TM0 | REG_TM7_TM6_TM5_TM4 | REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET)
+    }
+
+    fn read(&self, addr: usize, registers: &[u4]) -> Result<u4, EmulationError> {
+        Ok(match addr {
+            REG_TM3_TM2_TM1_TM0 => self.counter().nibble(0),
+            REG_TM7_TM6_TM5_TM4 => self.counter().nibble(1),
+            _ => registers[addr],
+        })
+    }
+
+    fn write(&mut self, addr: usize, value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        if addr == REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET && value.is_set(u4![0b0010]) {
+            self.ticks = 0;
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, delta_cycles: u32, _registers: &mut [u4]) {
+        let ticks = self.ticks + delta_cycles;
+        let mut factor = u4![0];
+        for (bit, period) in [(0b0001, TIMER_32HZ_CYCLES), (0b0010, TIMER_8HZ_CYCLES), (0b0100, TIMER_2HZ_CYCLES), (0b1000, TIMER_1HZ_CYCLES)] {
+            if ticks / period != self.ticks / period {
+                factor = factor | u4![bit];
+            }
+        }
+        self.ticks = ticks % TIMER_1HZ_CYCLES;
+
+        if factor != u4![0] {
+            self.pending = Some(factor);
+        }
+    }
+
+    fn interrupt_request(&mut self) -> Option<(Interrupt, u4)> {
+        self.pending.take().map(|factor| (Interrupt::ClockTimer, factor))
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.ticks);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ticks = reader.u32()?;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+/// 8-bit down counter clocked at 256 Hz, reloaded and raising its factor when it reaches 0.
+#[derive(Clone, Default)]
+pub struct ProgTimer {
+    ticks: u32,
+    pending: bool,
+}
+
+impl ProgTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn reload(registers: &mut [u4]) {
+        registers[REG_PROG_TIMER_DATA_LO] = registers[REG_PROG_TIMER_RELOAD_DATA_LO];
+        registers[REG_PROG_TIMER_DATA_HI] = registers[REG_PROG_TIMER_RELOAD_DATA_HI];
+    }
+}
+
+impl Peripheral for ProgTimer {
+    fn handles(&self, addr: usize) -> bool {
+        matches!(addr, REG_PROG_TIMER_DATA_LO..=REG_PROG_TIMER_RELOAD_DATA_HI | REG_PROG_TIMER_RESET_ENABLE | REG_PTCOUT_PTC2_PTC1_PTC0)
+    }
+
+    fn write(&mut self, addr: usize, value: u4, registers: &mut [u4]) -> Result<(), EmulationError> {
+        match addr {
+            REG_PROG_TIMER_RESET_ENABLE if value.is_set(u4![0b0010]) => {
+                Self::reload(registers);
+                self.ticks = 0;
+            },
+            // TODO: other input clocks
+            REG_PTCOUT_PTC2_PTC1_PTC0 if value & u4![0b0111] != u4![0x2] => {
+                return Err(EmulationError::UnsupportedIoValue { addr, value, reason: "only the 256 Hz programmable timer clock is emulated" });
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, delta_cycles: u32, registers: &mut [u4]) {
+        if !registers[REG_PROG_TIMER_RESET_ENABLE].is_set(u4![0b0001]) {
+            return;
+        }
+
+        self.ticks += delta_cycles;
+        while self.ticks >= TIMER_256HZ_CYCLES {
+            self.ticks -= TIMER_256HZ_CYCLES;
+
+            let timer_data = u8::from_be_nibbles(vec![registers[REG_PROG_TIMER_DATA_HI], registers[REG_PROG_TIMER_DATA_LO]]).wrapping_sub(1);
+            if timer_data == 0 {
+                Self::reload(registers);
+                self.pending = true;
+            } else {
+                registers[REG_PROG_TIMER_DATA_LO] = timer_data.nibble(0);
+                registers[REG_PROG_TIMER_DATA_HI] = timer_data.nibble(1);
+            }
+        }
+    }
+
+    fn interrupt_request(&mut self) -> Option<(Interrupt, u4)> {
+        std::mem::take(&mut self.pending).then_some((Interrupt::ProgTimer, u4![0b0001]))
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.ticks);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ticks = reader.u32()?;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prog_timer_reload() {
+        let mut registers = [u4![0]; 4096];
+        registers[REG_PROG_TIMER_RELOAD_DATA_LO] = u4![0x2];
+
+        let mut timer = ProgTimer::new();
+        timer.write(REG_PROG_TIMER_RESET_ENABLE, u4![0b0010], &mut registers).unwrap();
+        assert_eq!(registers[REG_PROG_TIMER_DATA_LO], u4![0x2]);
+
+        registers[REG_PROG_TIMER_RESET_ENABLE] = u4![0b0001];
+        timer.tick(TIMER_256HZ_CYCLES, &mut registers);
+        assert_eq!(registers[REG_PROG_TIMER_DATA_LO], u4![0x1]);
+        assert_eq!(timer.interrupt_request(), None);
+
+        timer.tick(TIMER_256HZ_CYCLES, &mut registers);
+        assert_eq!(registers[REG_PROG_TIMER_DATA_LO], u4![0x2]);
+        assert_eq!(timer.interrupt_request(), Some((Interrupt::ProgTimer, u4![0b0001])));
+        assert_eq!(timer.interrupt_request(), None);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    prelude::*,
+    error::EmulationError,
+    interrupt::Interrupt,
+    memory::REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET,
+    peripheral::Peripheral,
+    savestate::{SaveStateError, StateReader, StateWriter},
+};
+
+// The watchdog is a 4-bit counter clocked at 4 Hz, overflowing every 4 seconds unless WDRST is written.
+const WATCHDOG_OVERFLOW_CYCLES: u32 = 4 * 32768;
+
+/// What happens when the ROM fails to reset the watchdog in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Non-maskable interrupt to 0x100, which also releases HALT.
+    Nmi,
+    /// Full CPU and I/O reset.
+    Reset,
+}
+
+/// Watchdog timer. It's a mask option on the real chip, so it's disabled by default.
+#[derive(Clone)]
+pub struct Watchdog {
+    pub enabled: bool,
+    pub action: WatchdogAction,
+    ticks: u32,
+    overflow: Option<WatchdogAction>,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            action: WatchdogAction::Nmi,
+            ticks: 0,
+            overflow: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ticks = 0;
+    }
+
+    /// Whether the watchdog asked for a full reset, which only the CPU can carry out.
+    pub fn take_reset(&mut self) -> bool {
+        if self.overflow == Some(WatchdogAction::Reset) {
+            self.overflow = None;
+            return true;
+        }
+        false
+    }
+
+    /// Advances the counter, returning the action to take when it overflows.
+    pub fn count(&mut self, delta_cycles: u32) -> Option<WatchdogAction> {
+        if !self.enabled {
+            return None;
+        }
+
+        self.ticks += delta_cycles;
+        if self.ticks < WATCHDOG_OVERFLOW_CYCLES {
+            return None;
+        }
+
+        self.ticks -= WATCHDOG_OVERFLOW_CYCLES;
+        Some(self.action)
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Peripheral for Watchdog {
+    fn handles(&self, addr: usize) -> bool {
+        addr == REG_CLOCK_TIMER_WATCHDOG_TIMER_RESET
+    }
+
+    fn write(&mut self, _addr: usize, value: u4, _registers: &mut [u4]) -> Result<(), EmulationError> {
+        if value.is_set(u4![0b0001]) {
+            Watchdog::reset(self);
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, delta_cycles: u32, _registers: &mut [u4]) {
+        if let Some(action) = self.count(delta_cycles) {
+            self.overflow = Some(action);
+        }
+    }
+
+    fn interrupt_request(&mut self) -> Option<(Interrupt, u4)> {
+        if self.overflow == Some(WatchdogAction::Nmi) {
+            self.overflow = None;
+            return Some((Interrupt::Watchdog, u4![0]));
+        }
+        None
+    }
+
+    fn reset(&mut self) {
+        Watchdog::reset(self);
+        self.overflow = None;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.ticks);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ticks = reader.u32()?;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,43 @@
+use std::{env, fs, path::Path};
+
+use rustchi_core::{chip::ChipProfile, golden::{self, GoldenError, Script}};
+
+// Replays every `tests/golden/*.script` against the ROM and compares the LCD with the goldens
+// in the directory named after the script. The ROM isn't distributed, so this only runs with
+// `cargo test -- --ignored`, reading the ROM from RUSTCHI_ROM or www/rom.bin. Set
+// UPDATE_GOLDEN=1 to record the goldens.
+#[test]
+#[ignore = "needs a ROM, see RUSTCHI_ROM"]
+fn golden_images() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let rom_path = env::var("RUSTCHI_ROM").map_or(root.join("../www/rom.bin"), Into::into);
+    let rom = fs::read(&rom_path).unwrap_or_else(|e| panic!("can't read the ROM at {}: {}", rom_path.display(), e));
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = vec![];
+    for entry in fs::read_dir(root.join("tests/golden")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() != Some("script".as_ref()) {
+            continue;
+        }
+
+        let goldens = path.with_extension("");
+        if update {
+            fs::create_dir_all(&goldens).unwrap();
+        } else if !goldens.is_dir() {
+            failures.push(format!("{}: no goldens, record them with UPDATE_GOLDEN=1", path.display()));
+            continue;
+        }
+
+        let script = Script::parse(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        match golden::run(rom.clone(), ChipProfile::e0c6s46(), &script, &goldens, update) {
+            Ok(()) => (),
+            Err(GoldenError::Mismatches(mismatches)) =>
+                failures.extend(mismatches.iter().map(|m| format!("{}: {}", path.display(), m))),
+            Err(error) => failures.push(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    assert!(failures.is_empty(), "golden mismatches:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,141 @@
+use std::{fs::File, io::{self, Write}, path::Path};
+
+use rustchi_core::frame::Frame;
+
+use crate::screenshot::{self, Palette, IMAGE_HEIGHT, IMAGE_WIDTH};
+
+/// Collects one LCD frame per emulated frame and encodes them as an animation.
+pub struct Recorder {
+    fps: u32,
+    frames: Vec<Frame>,
+}
+
+impl Recorder {
+    pub fn new(fps: u32) -> Self {
+        Self {
+            fps,
+            frames: vec![],
+        }
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Consecutive identical pictures are merged into a single longer frame.
+    fn pictures(&self, scale: usize) -> Vec<(Vec<u8>, u32)> {
+        let mut pictures: Vec<(Vec<u8>, u32)> = vec![];
+        for frame in &self.frames {
+            let pixels = screenshot::render_scaled(frame, scale);
+            match pictures.last_mut() {
+                Some((last, length)) if *last == pixels => *length += 1,
+                _ => pictures.push((pixels, 1)),
+            }
+        }
+        pictures
+    }
+
+    fn check_not_empty(&self) -> io::Result<()> {
+        if self.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no frames recorded"));
+        }
+        Ok(())
+    }
+
+    /// Encodes the recording as a looping GIF, using the intensity as palette index.
+    pub fn write_gif(&self, writer: impl Write, scale: usize, palette: Palette) -> io::Result<()> {
+        self.check_not_empty()?;
+
+        let colors: Vec<u8> = (0..=255).flat_map(|intensity| palette.rgb(intensity)).collect();
+        let (width, height) = ((IMAGE_WIDTH * scale) as u16, (IMAGE_HEIGHT * scale) as u16);
+        let mut encoder = gif::Encoder::new(writer, width, height, &colors).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+
+        // GIF delays are in hundredths of a second, so round the end of each frame rather
+        // than each delay to keep the total duration right.
+        let mut elapsed = 0;
+        for (pixels, length) in self.pictures(scale) {
+            let start = elapsed * 100 / self.fps;
+            elapsed += length;
+            let end = elapsed * 100 / self.fps;
+
+            let mut frame = gif::Frame::from_indexed_pixels(width, height, pixels, None);
+            frame.delay = (end - start) as u16;
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the recording as a looping APNG.
+    pub fn write_apng(&self, writer: impl Write, scale: usize, palette: Palette) -> io::Result<()> {
+        self.check_not_empty()?;
+
+        let pictures = self.pictures(scale);
+        let mut encoder = png::Encoder::new(writer, (IMAGE_WIDTH * scale) as u32, (IMAGE_HEIGHT * scale) as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(pictures.len() as u32, 0)?;
+
+        let mut writer = encoder.write_header()?;
+        for (pixels, length) in pictures {
+            writer.set_frame_delay(length as u16, self.fps as u16)?;
+            let rgb: Vec<u8> = pixels.into_iter().flat_map(|intensity| palette.rgb(intensity)).collect();
+            writer.write_image_data(&rgb)?;
+        }
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Saves as APNG when the extension is `png` or `apng`, as GIF otherwise.
+    pub fn save(&self, path: &Path, scale: usize, palette: Palette) -> io::Result<()> {
+        let file = File::create(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("png" | "apng") => self.write_apng(file, scale, palette),
+            _ => self.write_gif(file, scale, palette),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rustchi_core::{frame::{FRAME_HEIGHT, FRAME_WIDTH}, icons::Icons};
+
+    use super::*;
+
+    #[test]
+    fn gif_timing() {
+        let blank = Frame {
+            dots: [[0; FRAME_WIDTH]; FRAME_HEIGHT],
+            icons: Icons::empty(),
+            icon_intensity: [0; 8],
+            dirty: true,
+        };
+        let mut lit = blank.clone();
+        lit.dots[3][4] = 255;
+
+        let mut recorder = Recorder::new(30);
+        for frame in [&blank, &blank, &lit, &lit, &lit, &blank] {
+            recorder.push(frame.clone());
+        }
+
+        let mut bytes = vec![];
+        recorder.write_gif(&mut bytes, 1, Palette::Gray).unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(bytes.as_slice()).unwrap();
+        let mut delays = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, vec![6, 10, 4]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{io::{self, Write}, str::FromStr};
+
+use rustchi_core::{
+    frame::{Frame, FRAME_HEIGHT, FRAME_WIDTH},
+    icons::Icons,
+};
+
+// Layout in LCD dots: a row of icons above and below the matrix, each icon a 4x2 block.
+const ICON_ROW_HEIGHT: usize = 3;
+const ICON_WIDTH: usize = 4;
+const ICON_HEIGHT: usize = 2;
+const ICON_COLUMNS: [usize; 4] = [2, 10, 18, 26];
+const TOP_ICONS: [Icons; 4] = [Icons::FOOD, Icons::LIGHT, Icons::GAME, Icons::MEDICINE];
+const BOTTOM_ICONS: [Icons; 4] = [Icons::BATHROOM, Icons::STATUS, Icons::TRAINING, Icons::ATTENTION];
+
+pub const IMAGE_WIDTH: usize = FRAME_WIDTH;
+pub const IMAGE_HEIGHT: usize = FRAME_HEIGHT + 2 * ICON_ROW_HEIGHT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Palette {
+    /// Dark dots on the greenish background of the P1 LCD.
+    #[default]
+    Lcd,
+    /// Black on white.
+    Gray,
+}
+
+impl Palette {
+    fn colors(self) -> ([u8; 3], [u8; 3]) {
+        match self {
+            Palette::Lcd => ([0x1F, 0x2A, 0x1C], [0xB7, 0xC4, 0xA5]),
+            Palette::Gray => ([0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]),
+        }
+    }
+
+    /// Blends between the background and the dot color.
+    pub fn rgb(self, intensity: u8) -> [u8; 3] {
+        let (on, off) = self.colors();
+        std::array::from_fn(|i| {
+            let on = u32::from(on[i]) * u32::from(intensity);
+            let off = u32::from(off[i]) * u32::from(255 - intensity);
+            ((on + off) / 255) as u8
+        })
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "lcd" => Ok(Palette::Lcd),
+            "gray" => Ok(Palette::Gray),
+            _ => Err(format!("unknown palette `{}`, expected `lcd` or `gray`", name)),
+        }
+    }
+}
+
+/// Intensity of each pixel of the unscaled image, icons included.
+pub fn render(frame: &Frame) -> [[u8; IMAGE_WIDTH]; IMAGE_HEIGHT] {
+    let mut pixels = [[0; IMAGE_WIDTH]; IMAGE_HEIGHT];
+
+    for (y, row) in frame.dots.iter().enumerate() {
+        pixels[y + ICON_ROW_HEIGHT][..FRAME_WIDTH].copy_from_slice(row);
+    }
+
+    let icon_rows = [(0, TOP_ICONS), (IMAGE_HEIGHT - ICON_HEIGHT, BOTTOM_ICONS)];
+    for (top, icons) in icon_rows {
+        for (icon, left) in icons.into_iter().zip(ICON_COLUMNS) {
+            for row in pixels.iter_mut().skip(top).take(ICON_HEIGHT) {
+                row[left..left + ICON_WIDTH].fill(frame.icon(icon));
+            }
+        }
+    }
+
+    pixels
+}
+
+/// Intensity of each pixel, each LCD dot drawn as a `scale`x`scale` square.
+pub fn render_scaled(frame: &Frame, scale: usize) -> Vec<u8> {
+    render(frame).iter().flat_map(|row| {
+        let line: Vec<u8> = row.iter()
+            .flat_map(|intensity| [*intensity].repeat(scale))
+            .collect();
+        line.repeat(scale)
+    }).collect()
+}
+
+/// RGB bytes of the scaled image.
+pub fn render_rgb(frame: &Frame, scale: usize, palette: Palette) -> Vec<u8> {
+    render_scaled(frame, scale).into_iter().flat_map(|intensity| palette.rgb(intensity)).collect()
+}
+
+/// Encodes the frame as a PNG.
+pub fn write_png(writer: impl Write, frame: &Frame, scale: usize, palette: Palette) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, (IMAGE_WIDTH * scale) as u32, (IMAGE_HEIGHT * scale) as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&render_rgb(frame, scale, palette))?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let mut frame = Frame {
+            dots: [[0; FRAME_WIDTH]; FRAME_HEIGHT],
+            icons: Icons::ATTENTION,
+            icon_intensity: [0; 8],
+            dirty: true,
+        };
+        frame.dots[0][1] = 255;
+        frame.icon_intensity[7] = 255;
+
+        let pixels = render(&frame);
+        assert_eq!(pixels[ICON_ROW_HEIGHT][1], 255);
+        assert_eq!(pixels[IMAGE_HEIGHT - 1][26..31], [255, 255, 255, 255, 0]);
+        assert_eq!(pixels.iter().flatten().filter(|p| **p == 255).count(), 1 + ICON_WIDTH * ICON_HEIGHT);
+
+        assert_eq!(render_rgb(&frame, 2, Palette::Gray).len(), IMAGE_WIDTH * IMAGE_HEIGHT * 4 * 3);
+    }
+}
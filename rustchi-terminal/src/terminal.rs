@@ -1,33 +1,112 @@
+pub mod recording;
+pub mod screenshot;
+
+use std::{fmt, fs::{self, File}, io, path::{Path, PathBuf}};
+
 use rustchi_core::{
     interpreter::Interpreter,
     change::{Change, Register, Memory},
+    frame::{Frame, FRAME_HEIGHT},
+    icons::Icons,
     input::Button,
+    interrupt::Interrupt,
+    movie::{Movie, MovieError, MoviePlayer, MovieRecorder},
+    error::ErrorPolicy,
+    svd::Battery,
+    timer::ClockTimer,
 };
-use rustchi_core::primitive::{u1, u4};
+use rustchi_core::primitive::u4;
+#[cfg(feature = "scripting")]
+use rustchi_core::script::{Script, ScriptError};
 
 use ansi_term::{Colour, Style};
-use clap::{ArgAction, Parser};
 use game_time::{step, GameClock, FloatDuration, GameTime};
 use itertools::Itertools;
-
-const CYCLES_PER_FRAME: u64 = 32_768 / 30;
+use recording::Recorder;
+use screenshot::Palette;
+
+const FPS: u64 = 30;
+const FRESH_BATTERY_VOLTAGE: f64 = 3.0;
+const LOW_BATTERY_VOLTAGE: f64 = 2.1;
+const TURBO_SPEED: f64 = 8.0;
+// Rate of `State::cycles`, the OSC1 oscillator
+const CYCLES_PER_SECOND: u64 = 32_768;
+const TOP_ICONS: [(Icons, &str); 4] = [(Icons::FOOD, "󰩰"), (Icons::LIGHT, "󰛨"), (Icons::GAME, "󰡓"), (Icons::MEDICINE, "󰐂")];
+const BOTTOM_ICONS: [(Icons, &str); 4] = [(Icons::BATHROOM, "󰇥"), (Icons::STATUS, "󰓅"), (Icons::TRAINING, "󰮯"), (Icons::ATTENTION, "\u{eb54}")];
 const BUTTON_A_LABEL: &str = "|A|";
 const BUTTON_B_LABEL: &str = "|B|";
 const BUTTON_C_LABEL: &str = "|C|";
+// Where `print_screen` draws the labels, from the top left corner of the panels
+const BUTTON_ROW: usize = FRAME_HEIGHT / 2 + 7;
+const BUTTON_COLUMNS: [(Button, usize); 3] = [(Button::A, 7), (Button::B, 15), (Button::C, 23)];
+
+/// How the terminal runs and what it shows.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Stops once this many instructions were executed.
+    pub breakpoint: Option<u32>,
+    /// Prints only the PC and the next opcode, instead of the panels.
+    pub short: bool,
+    pub panels: Panels,
+    /// Stops on I/O accesses and opcodes the emulator doesn't handle instead of ignoring them.
+    pub strict: bool,
+    /// Emulated time per real time, 1.0 being the real speed. Headless runs ignore it.
+    pub speed: f64,
+    /// Where save states are written to and read from.
+    pub save: PathBuf,
+    /// Emulated milliseconds a tapped button stays pressed.
+    pub tap_ms: u32,
+    /// Runs without a display until this many cycles have elapsed, then saves a screenshot.
+    pub screenshot_at: Option<u32>,
+    pub screenshot: PathBuf,
+    /// Runs without a display and records the frames in `record_from..record_to` to this path.
+    pub record: Option<PathBuf>,
+    pub record_from: u32,
+    pub record_to: Option<u32>,
+    /// Size in pixels of an LCD dot in screenshots and recordings.
+    pub scale: usize,
+    pub palette: Palette,
+}
 
-#[derive(Debug, Parser)]
-struct Cli {
-    #[arg(short, long)]
-    breakpoint: Option<u32>,
-
-    #[arg(short, long)]
-    short: bool,
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            breakpoint: None,
+            short: false,
+            panels: Panels::default(),
+            strict: false,
+            speed: 1.0,
+            save: PathBuf::from("rustchi.sav"),
+            tap_ms: 200,
+            screenshot_at: None,
+            screenshot: PathBuf::from("screenshot.png"),
+            record: None,
+            record_from: 0,
+            record_to: None,
+            scale: 8,
+            palette: Palette::default(),
+        }
+    }
+}
 
-    #[arg(short, long, action=ArgAction::SetFalse)]
-    debugger: bool,
+/// Panels printed side by side, from left to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Panels {
+    pub lcd: bool,
+    pub disassembler: bool,
+    pub registers: bool,
+    pub memory: bool,
+}
 
-    #[arg(short, long, action=ArgAction::SetFalse)]
-    lcd: bool,
+impl Default for Panels {
+    fn default() -> Self {
+        Self {
+            lcd: true,
+            disassembler: true,
+            registers: true,
+            memory: true,
+        }
+    }
 }
 
 pub trait FFI {
@@ -96,7 +175,7 @@ struct Clock {
 impl Clock {
     pub fn new() -> Self {
         let mut clock = GameClock::new();
-        let lcd_fps = FloatDuration::seconds(1. / 30.);
+        let lcd_fps = FloatDuration::seconds(1. / FPS as f64);
         let lcd_time = clock.tick(&step::ConstantStep::new(lcd_fps));
 
         Self {
@@ -108,28 +187,226 @@ impl Clock {
 }
 
 pub struct Terminal<T> {
-    args: Cli,
+    options: Options,
     pub printer: T,
     interpreter: Interpreter,
     clock: Clock,
+    frame: Option<Frame>,
+    recorder: Option<Recorder>,
+    turbo: bool,
+    // Emulated frames owed to the speed, carried over between shown frames
+    frames_due: f64,
+    // Tapped buttons and the cycle they were pressed at
+    taps: Vec<(Button, u32)>,
+    movie: Option<MovieRecorder>,
+    player: Option<MoviePlayer>,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
 }
 
 impl<T> Terminal<T> {
-    pub fn new(printer: T, interpreter: Interpreter) -> Self {
+    pub fn new(printer: T, mut interpreter: Interpreter, options: Options) -> Self {
+        interpreter.set_error_policy(if options.strict { ErrorPolicy::Strict } else { ErrorPolicy::Lenient });
+
         Self {
-            args: Cli::parse(),
+            options,
             printer,
             interpreter,
             clock: Clock::new(),
+            frame: None,
+            recorder: None,
+            turbo: false,
+            frames_due: 0.0,
+            taps: vec![],
+            movie: None,
+            player: None,
+            #[cfg(feature = "scripting")]
+            script: None,
         }
     }
 
     pub fn press_button(&mut self, button: Button) {
-        self.interpreter.press_button(button);
+        self.taps.retain(|(tapped, _)| *tapped != button);
+        self.set_button(button, true);
     }
 
     pub fn release_button(&mut self, button: Button) {
-        self.interpreter.release_button(button);
+        self.taps.retain(|(tapped, _)| *tapped != button);
+        self.set_button(button, false);
+    }
+
+    // Buttons come from the movie while one plays
+    fn set_button(&mut self, button: Button, pressed: bool) {
+        match (&mut self.movie, pressed) {
+            _ if self.player.is_some() => (),
+            (Some(movie), true) => movie.press(&mut self.interpreter, button),
+            (Some(movie), false) => movie.release(&mut self.interpreter, button),
+            (None, true) => self.interpreter.press_button(button),
+            (None, false) => self.interpreter.release_button(button),
+        }
+    }
+
+    /// Presses the button and releases it `tap_ms` emulated milliseconds later,
+    /// for terminals that don't report key releases.
+    pub fn tap_button(&mut self, button: Button) {
+        self.press_button(button);
+        self.taps.push((button, self.interpreter.state.cycles));
+    }
+
+    pub fn is_button_pressed(&self, button: Button) -> bool {
+        self.interpreter.state.input.is_button_pressed(button)
+    }
+
+    fn release_taps(&mut self) {
+        let now = self.interpreter.state.cycles;
+        let hold = (u64::from(self.options.tap_ms) * CYCLES_PER_SECOND / 1000) as u32;
+        let (released, held) = self.taps.iter().partition(|(_, at)| now.wrapping_sub(*at) >= hold);
+        self.taps = held;
+        for (button, _) in released {
+            self.set_button(button, false);
+        }
+    }
+
+    /// Records the buttons from now on, until `finish_movie`.
+    pub fn record_movie(&mut self) {
+        self.movie = Some(MovieRecorder::new(&self.interpreter));
+    }
+
+    pub fn finish_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|movie| movie.finish(&self.interpreter))
+    }
+
+    /// Restores the movie's start state and replays its buttons, ignoring the user's until it ends.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+        self.player = Some(MoviePlayer::start(&mut self.interpreter, movie)?);
+        Ok(())
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        self.player.is_some()
+    }
+
+    /// Runs the script's top level now, and its hooks as the emulation goes.
+    #[cfg(feature = "scripting")]
+    pub fn load_script(&mut self, source: &str) -> Result<(), ScriptError> {
+        self.script = Some(Script::load(source, &mut self.interpreter)?);
+        self.apply_script_buttons();
+        Ok(())
+    }
+
+    // Through `set_button`, like the keyboard
+    #[cfg(feature = "scripting")]
+    fn apply_script_buttons(&mut self) {
+        let buttons = self.script.as_mut().map(Script::take_buttons).unwrap_or_default();
+        for (button, pressed) in buttons {
+            self.set_button(button, pressed);
+        }
+    }
+
+    /// The button whose label is at `column` and `row` of the panels, for mouse clicks.
+    pub fn button_at(&self, column: usize, row: usize) -> Option<Button> {
+        if self.options.short || !self.options.panels.lcd || row != BUTTON_ROW {
+            return None;
+        }
+        BUTTON_COLUMNS.iter()
+            .find(|(_, start)| (*start..start + BUTTON_A_LABEL.len()).contains(&column))
+            .map(|(button, _)| *button)
+    }
+
+    /// Does nothing during a movie, which doesn't record the battery.
+    pub fn toggle_low_battery(&mut self) {
+        if self.movie.is_some() || self.player.is_some() {
+            return;
+        }
+        let battery = self.interpreter.battery();
+        let voltage = if battery.voltage > LOW_BATTERY_VOLTAGE { LOW_BATTERY_VOLTAGE } else { FRESH_BATTERY_VOLTAGE };
+        self.interpreter.set_battery(Battery { voltage, ..battery });
+    }
+
+    /// Runs at `TURBO_SPEED` times the configured speed.
+    pub fn toggle_turbo(&mut self) {
+        self.turbo = !self.turbo;
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        self.turbo
+    }
+
+    fn speed(&self) -> f64 {
+        if self.turbo { self.options.speed * TURBO_SPEED } else { self.options.speed }
+    }
+
+    pub fn toggle_lcd(&mut self) {
+        self.options.panels.lcd = !self.options.panels.lcd;
+    }
+
+    /// Shows or hides the disassembler, registers and memory panels together.
+    pub fn toggle_debugger(&mut self) {
+        let panels = &mut self.options.panels;
+        let shown = !(panels.disassembler || panels.registers || panels.memory);
+        panels.disassembler = shown;
+        panels.registers = shown;
+        panels.memory = shown;
+    }
+
+    /// Does nothing during a movie, which wouldn't replay the same otherwise.
+    pub fn reset(&mut self) {
+        if self.movie.is_none() && self.player.is_none() {
+            self.interpreter.reset();
+        }
+    }
+
+    /// Writes a save state to the save path.
+    pub fn save_state(&self) -> io::Result<&Path> {
+        fs::write(&self.options.save, self.interpreter.save_state())?;
+        Ok(&self.options.save)
+    }
+
+    /// Restores the save state at the save path.
+    pub fn load_state(&mut self) -> io::Result<()> {
+        if self.movie.is_some() || self.player.is_some() {
+            return Err(io::Error::other("can't load a state during a movie"));
+        }
+        let bytes = fs::read(&self.options.save)?;
+        self.interpreter.load_state(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.options.screenshot_at.is_some() || self.options.record.is_some()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Starts recording, or stops and saves a GIF named after the current cycle count.
+    pub fn toggle_recording(&mut self) -> io::Result<Option<PathBuf>> {
+        match self.recorder.take() {
+            None => {
+                self.recorder = Some(Recorder::new(FPS as u32));
+                Ok(None)
+            },
+            Some(recorder) => {
+                let path = PathBuf::from(format!("recording-{}.gif", self.interpreter.state.cycles));
+                recorder.save(&path, self.options.scale, self.options.palette)?;
+                Ok(Some(path))
+            },
+        }
+    }
+
+    /// Saves the last displayed frame as a PNG named after the current cycle count.
+    pub fn screenshot(&mut self) -> io::Result<PathBuf> {
+        let path = PathBuf::from(format!("screenshot-{}.png", self.interpreter.state.cycles));
+        self.save_screenshot(&path)?;
+        Ok(path)
+    }
+
+    fn save_screenshot(&mut self, path: &Path) -> io::Result<()> {
+        let frame = match &self.frame {
+            Some(frame) => frame.clone(),
+            None => self.interpreter.frame(),
+        };
+        screenshot::write_png(File::create(path)?, &frame, self.options.scale, self.options.palette)
     }
 }
 
@@ -140,9 +417,9 @@ macro_rules! style {
 }
 
 impl<T> Terminal<T> where T: FFI {
-    fn print_panels(&self, interpreter: &Interpreter) {
+    fn print_panels(&self, interpreter: &Interpreter, frame: &Frame) {
 
-        if self.args.short {
+        if self.options.short {
             let opcode = interpreter.next_opcode();
             println!("{:#06X} {}", interpreter.state.pc(), opcode);
             return;
@@ -150,55 +427,49 @@ impl<T> Terminal<T> where T: FFI {
 
         let mut panels = Panel::new(0);
 
-        panels = if self.args.lcd {
-            panels.zip(self.print_screen(&interpreter))
-        } else {
-            panels
-        };
-
-        panels = if self.args.debugger {
-            let disassembler = self.print_disassembler(&interpreter);
-            let registers = self.print_registers(&interpreter);
-            let memory = self.print_memory(&interpreter);
-            panels.zip(disassembler).zip(registers).zip(memory)
-        } else {
-            panels
-        };
+        let shown = self.options.panels;
+        if shown.lcd {
+            panels = panels.zip(self.print_screen(&interpreter, frame));
+        }
+        if shown.disassembler {
+            panels = panels.zip(self.print_disassembler(&interpreter));
+        }
+        if shown.registers {
+            panels = panels.zip(self.print_registers(&interpreter));
+        }
+        if shown.memory {
+            panels = panels.zip(self.print_memory(&interpreter));
+        }
 
         panels.print(&self.printer);
     }
 
-    fn print_screen(&self, interpreter: &Interpreter) -> Panel {
-        let lcd = interpreter.state.memory.lcd.borrow();
-
+    fn print_screen(&self, interpreter: &Interpreter, frame: &Frame) -> Panel {
         let mut panel = Panel::new(34);
-        let on = Colour::Fixed(255);
-        let off = Colour::Fixed(239);
-        let both_off = off.on(off);
-        let top_on = on.on(off);
-        let bottom_on = off.on(on);
-        let both_on = on.on(on);
+
+        // Shades of the 239-255 grayscale ramp
+        let shade = |intensity: u8| Colour::Fixed(239 + (u16::from(intensity) * 16 / 255) as u8);
+        let icons = |icons: &[(Icons, &str)]| {
+            let glyphs = icons.iter().map(|(icon, glyph)|
+                shade(frame.icon(*icon)).paint(*glyph).to_string()
+            ).join("      ");
+            format!("     {}     ", glyphs)
+        };
 
         panel.push_top();
-        panel.push(off.paint("     󰩰      󰛨      󰡓           ").to_string());
+        panel.push(icons(&TOP_ICONS));
         panel.push("".to_string());
-        for y in (0..16).step_by(2)  {
-            let top = lcd[y].iter().take(32);
-            let bottom = lcd[y+1].iter().take(32);
+        for y in (0..FRAME_HEIGHT).step_by(2)  {
+            let top = frame.dots[y].iter();
+            let bottom = frame.dots[y+1].iter();
             let row = top.zip(bottom).map(|(a, b)|
-                match (*a, *b) {
-                    (u1::OFF, u1::OFF) => both_off.paint("▀"),
-                    (u1::ON, u1::OFF) => top_on.paint("▀"),
-                    (u1::OFF, u1::ON) => bottom_on.paint("▀"),
-                    (u1::ON, u1::ON) => both_on.paint("▀"),
-                    _ => panic!(),
-                }.to_string()
+                shade(*a).on(shade(*b)).paint("▀").to_string()
             ).join("");
 
             panel.push(row);
         }
         panel.push("".to_string());
-        panel.push(off.paint("     󰇥      󰓅      󰮯           ").to_string());
+        panel.push(icons(&BOTTOM_ICONS));
         panel.push_bottom();
 
         let button_on = Colour::Black.on(Colour::Fixed(255));
@@ -260,12 +531,13 @@ impl<T> Terminal<T> where T: FFI {
         panel.push_with_style(format!(" F    {:#X}", interpreter.state.flags), style!(changes, Change::Flags(_), on, off));
         panel.push_bottom();
 
-        let tick: u32 = (interpreter.state.memory.clock_timer_ticks / 8192) + 1;
+        let tick: u32 = (interpreter.state.memory.bus.get::<ClockTimer>().map_or(0, |timer| timer.ticks()) / 8192) + 1;
         panel.push_top();
         panel.push(format!(" W1 {:01X}", interpreter.state.memory.get(0xF10)));
         panel.push(format!(" W2 {:01X}", interpreter.state.memory.get(0xF12)));
-        panel.push(format!(" W3 {:01X}", interpreter.state.memory.get(0xF00)));
+        panel.push(format!(" W3 {:01X}", interpreter.state.memory.interrupts.peek_factor(Interrupt::ClockTimer)));
         panel.push(format!(" W4 {}", ".".repeat(tick.try_into().unwrap())));
+        panel.push(format!(" V  {:.2}", interpreter.battery().voltage));
         panel.push_bottom();
 
         panel
@@ -331,25 +603,143 @@ impl<T> Terminal<T> where T: FFI {
         panel
     }
 
+    /// Shows the last frame, then emulates as many frames as the speed asks for.
+    ///
+    /// Emulated frames always last 1/30 s of emulated time, so that recordings keep their timing
+    /// and the LCD persistence looks the same at any speed.
     pub fn run_frame(&mut self) {
         self.clock.lcd_time = self.clock.clock.tick(&step::ConstantStep::new(self.clock.lcd_fps));
 
-        self.print_panels(&self.interpreter);
+        self.show_frame();
+        self.frames_due += self.speed();
+        while self.frames_due >= 1.0 {
+            self.frames_due -= 1.0;
+            self.emulate_frame();
+            self.capture_frame();
+        }
+    }
+
+    /// Emulates one frame and shows it, for stepping through frames while paused.
+    pub fn advance_frame(&mut self) {
+        self.emulate_frame();
+        self.capture_frame();
+        self.show_frame();
+    }
+
+    /// Executes a single instruction and shows the result.
+    pub fn step_instruction(&mut self) {
+        self.step();
+        let frame = self.interpreter.frame();
+        self.print_panels(&self.interpreter, &frame);
+        self.frame = Some(frame);
+    }
+
+    fn show_frame(&mut self) {
+        if self.frame.is_none() {
+            self.capture_frame();
+        }
+        if let Some(frame) = &self.frame {
+            self.print_panels(&self.interpreter, frame);
+        }
+    }
 
+    // Ends the emulated frame, once per frame so that the LCD persistence averages over one
+    fn capture_frame(&mut self) {
+        let frame = self.interpreter.frame();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.push(frame.clone());
+        }
+        self.frame = Some(frame);
+    }
+
+    fn step(&mut self) {
+        if !self.taps.is_empty() {
+            self.release_taps();
+        }
+        if let Some(player) = &mut self.player {
+            player.update(&mut self.interpreter);
+            if player.is_finished() {
+                self.player = None;
+            }
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &mut self.script {
+            if script.is_breakpoint(self.interpreter.pc()) {
+                if let Err(error) = script.on_breakpoint(&mut self.interpreter) {
+                    self.stop(error);
+                }
+                self.apply_script_buttons();
+            }
+        }
+        if let Err(error) = self.interpreter.step() {
+            self.stop(error);
+        }
+    }
+
+    // Shows where the emulation stopped before panicking
+    fn stop(&mut self, reason: impl fmt::Display) -> ! {
+        self.printer.print("\n");
+        let frame = self.interpreter.frame();
+        self.print_panels(&self.interpreter, &frame);
+        panic!("{}", reason);
+    }
+
+    fn emulate_frame(&mut self) {
         loop {
-            if self.interpreter.cycle_counter < CYCLES_PER_FRAME {
-                self.interpreter.step();
+            let cycles_per_frame = u64::from(self.interpreter.state.clock_speed) / FPS;
+            if self.interpreter.cycle_counter < cycles_per_frame {
+                self.step();
             } else {
                 self.interpreter.reset_cycle_counter();
                 break;
             }
 
-            if self.args.breakpoint.is_some() && self.interpreter.state.tick == self.args.breakpoint.unwrap() {
-                self.printer.print("\n");
-                self.print_panels(&self.interpreter);
-                panic!("stop!");
+            if self.options.breakpoint.is_some() && self.interpreter.state.tick == self.options.breakpoint.unwrap() {
+                self.stop("stop!");
             }
         }
+
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &mut self.script {
+            if let Err(error) = script.on_frame(&mut self.interpreter) {
+                self.stop(error);
+            }
+            self.apply_script_buttons();
+        }
+    }
+
+    /// Emulates frames without printing anything, saving the screenshot and recording asked for on the command line.
+    pub fn run_headless(&mut self) -> io::Result<()> {
+        let mut screenshot_at = self.options.screenshot_at;
+        let record_range = self.options.record_to.map(|to| self.options.record_from..to);
+        let target = screenshot_at.into_iter().chain(self.options.record_to).max().unwrap_or(0);
+        let mut recorder = Recorder::new(FPS as u32);
+
+        loop {
+            self.emulate_frame();
+            let frame = self.interpreter.frame();
+            let cycles = self.interpreter.state.cycles;
+
+            if record_range.as_ref().is_some_and(|range| range.contains(&cycles)) {
+                recorder.push(frame.clone());
+            }
+            self.frame = Some(frame);
+
+            if screenshot_at.is_some_and(|at| cycles >= at) {
+                let path = self.options.screenshot.clone();
+                self.save_screenshot(&path)?;
+                screenshot_at = None;
+            }
+
+            if cycles >= target {
+                break;
+            }
+        }
+
+        match &self.options.record {
+            Some(path) => recorder.save(path, self.options.scale, self.options.palette),
+            None => Ok(()),
+        }
     }
 
     pub fn target_fps(&self) -> FloatDuration {
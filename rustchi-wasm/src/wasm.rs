@@ -7,8 +7,9 @@ use web_sys::{Request, Response};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::JsFuture;
 
+use rustchi_core::chip::ChipProfile;
 use rustchi_core::interpreter::Interpreter;
-use rustchi_terminal::{FFI, Terminal};
+use rustchi_terminal::{FFI, Options, Terminal};
 use rustchi_core::input::Button;
 
 #[wasm_bindgen]
@@ -42,14 +43,15 @@ pub struct Emulator {
 #[wasm_bindgen]
 impl Emulator {
     #[wasm_bindgen]
-    pub async fn load(rom_url: &str) -> Self {
+    pub async fn load(rom_url: &str) -> Result<Emulator, JsValue> {
         let bytes = fetch_url(rom_url).await;
 
-        let interpreter = Interpreter::load(bytes);
+        let interpreter = Interpreter::load(&bytes, ChipProfile::e0c6s46())
+            .map_err(|error| JsValue::from_str(&format!("can't load {}: {}", rom_url, error)))?;
 
-        Self {
-            terminal: Terminal::new(BrowserFFI::new(), interpreter),
-        }
+        Ok(Self {
+            terminal: Terminal::new(BrowserFFI::new(), interpreter, Options::default()),
+        })
     }
 
     #[wasm_bindgen]
@@ -57,6 +59,11 @@ impl Emulator {
         self.terminal.run_frame()
     }
 
+    #[wasm_bindgen]
+    pub fn toggle_low_battery(&mut self) {
+        self.terminal.toggle_low_battery()
+    }
+
     #[wasm_bindgen]
     pub fn press_button(&mut self, button: &str) {
 
@@ -0,0 +1,251 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+
+use clap::{Parser, ValueEnum};
+use rustchi_core::chip::ChipProfile;
+use rustchi_terminal::{screenshot::Palette, Options, Panels};
+use serde::Deserialize;
+
+use crate::keymap::{self, Action, Keymap};
+
+const DEFAULT_ROM: &str = "www/rom.bin";
+const DEFAULT_CONFIG: &str = "rustchi.toml";
+
+#[derive(Debug, Parser)]
+#[command(about = "Tamagotchi P1 emulator")]
+pub struct Cli {
+    /// ROM dump to run, as 16-bit words, packed 12-bit words or a tamalib header [default: www/rom.bin]
+    rom: Option<PathBuf>,
+
+    /// TOML file with defaults for the options below [default: rustchi.toml, if it exists]
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Chip the ROM runs on, E0C6S46 or E0C6S48 [default: E0C6S46]
+    #[arg(long)]
+    chip: Option<String>,
+
+    /// Where save states are written [default: the ROM path with a .sav extension]
+    #[arg(long, value_name = "PATH")]
+    save: Option<PathBuf>,
+
+    /// Emulation speed, 2 runs twice as fast as the real device [default: 1]
+    #[arg(long)]
+    speed: Option<f64>,
+
+    /// Emulated milliseconds a button stays pressed after a key press, in terminals that don't report key releases [default: 200]
+    #[arg(long, value_name = "MS")]
+    tap_ms: Option<u32>,
+
+    /// Panels to show, comma separated [default: all of them]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    panels: Option<Vec<Panel>>,
+
+    #[arg(short, long)]
+    breakpoint: Option<u32>,
+
+    /// Prints only the PC and the next opcode
+    #[arg(short, long)]
+    short: bool,
+
+    /// Stops on I/O accesses and opcodes the emulator doesn't handle instead of ignoring them
+    #[arg(long)]
+    strict: bool,
+
+    /// Runs without a display until this many cycles have elapsed, then saves a screenshot and exits
+    #[arg(long, value_name = "CYCLES")]
+    screenshot_at: Option<u32>,
+
+    /// Where the headless screenshot is saved
+    #[arg(long, default_value = "screenshot.png")]
+    screenshot: PathBuf,
+
+    /// Runs without a display and records the frames between --record-from and --record-to
+    #[arg(long, value_name = "PATH", requires = "record_to")]
+    record: Option<PathBuf>,
+
+    #[arg(long, value_name = "CYCLES", default_value_t = 0)]
+    record_from: u32,
+
+    #[arg(long, value_name = "CYCLES", requires = "record")]
+    record_to: Option<u32>,
+
+    /// Records the buttons pressed from the start state to this movie file, saved on quit
+    #[arg(long, value_name = "PATH", conflicts_with = "play_movie")]
+    record_movie: Option<PathBuf>,
+
+    /// Replays a movie file, recorded with the same ROM, before handing the buttons over
+    #[arg(long, value_name = "PATH")]
+    play_movie: Option<PathBuf>,
+
+    /// Rhai script run on load, with `on_frame` and `on_breakpoint` hooks
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["record_movie", "play_movie"])]
+    script: Option<PathBuf>,
+
+    /// Size in pixels of an LCD dot in screenshots and recordings [default: 8]
+    #[arg(long)]
+    scale: Option<usize>,
+
+    /// Colors of screenshots and recordings, lcd or gray [default: lcd]
+    #[arg(long)]
+    palette: Option<Palette>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Panel {
+    Lcd,
+    Disassembler,
+    Registers,
+    Memory,
+}
+
+/// Settings of the config file. Paths are relative to the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Config {
+    rom: Option<PathBuf>,
+    chip: Option<String>,
+    save: Option<PathBuf>,
+    speed: Option<f64>,
+    tap_ms: Option<u32>,
+    panels: Option<Vec<Panel>>,
+    strict: bool,
+    scale: Option<usize>,
+    palette: Option<String>,
+    /// Keys by action name, over the default keymap.
+    keys: BTreeMap<String, String>,
+}
+
+impl Config {
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let mut config: Self = toml::from_str(&text).map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        config.rom = config.rom.map(|rom| dir.join(rom));
+        config.save = config.save.map(|save| dir.join(save));
+        Ok(config)
+    }
+}
+
+/// What to run and how, from the command line over the config file over the defaults.
+pub struct Settings {
+    pub rom: PathBuf,
+    pub chip: ChipProfile,
+    pub options: Options,
+    pub keymap: Keymap,
+    pub record_movie: Option<PathBuf>,
+    pub play_movie: Option<PathBuf>,
+    #[cfg(feature = "scripting")]
+    pub script: Option<PathBuf>,
+}
+
+impl Settings {
+    pub fn from_args() -> Result<Self, String> {
+        let cli = Cli::parse();
+        let config = match &cli.config {
+            Some(path) => Config::read(path)?,
+            None if Path::new(DEFAULT_CONFIG).exists() => Config::read(Path::new(DEFAULT_CONFIG))?,
+            None => Config::default(),
+        };
+        Self::merge(cli, config)
+    }
+
+    fn merge(cli: Cli, config: Config) -> Result<Self, String> {
+        let rom = cli.rom.or(config.rom).unwrap_or_else(|| PathBuf::from(DEFAULT_ROM));
+
+        let chip = match cli.chip.or(config.chip) {
+            Some(name) => ChipProfile::by_name(&name).ok_or_else(|| format!("unknown chip `{}`", name))?,
+            None => ChipProfile::default(),
+        };
+
+        let palette = match (cli.palette, config.palette) {
+            (Some(palette), _) => palette,
+            (None, Some(name)) => name.parse()?,
+            (None, None) => Palette::default(),
+        };
+
+        let defaults = Options::default();
+        let panels = match cli.panels.or(config.panels) {
+            Some(shown) => Panels {
+                lcd: shown.contains(&Panel::Lcd),
+                disassembler: shown.contains(&Panel::Disassembler),
+                registers: shown.contains(&Panel::Registers),
+                memory: shown.contains(&Panel::Memory),
+            },
+            None => defaults.panels,
+        };
+
+        let options = Options {
+            breakpoint: cli.breakpoint,
+            short: cli.short,
+            panels,
+            strict: cli.strict || config.strict,
+            speed: cli.speed.or(config.speed).unwrap_or(defaults.speed),
+            save: cli.save.or(config.save).unwrap_or_else(|| rom.with_extension("sav")),
+            tap_ms: cli.tap_ms.or(config.tap_ms).unwrap_or(defaults.tap_ms),
+            screenshot_at: cli.screenshot_at,
+            screenshot: cli.screenshot,
+            record: cli.record,
+            record_from: cli.record_from,
+            record_to: cli.record_to,
+            scale: cli.scale.or(config.scale).unwrap_or(defaults.scale),
+            palette,
+        };
+
+        if options.speed <= 0.0 {
+            return Err(format!("speed must be positive, got {}", options.speed));
+        }
+
+        let mut keymap = Keymap::default();
+        for (name, key) in &config.keys {
+            let action = Action::from_name(name).ok_or_else(|| format!("unknown action `{}` in [keys]", name))?;
+            keymap.bind(action, keymap::parse_key(key)?);
+        }
+
+        Ok(Self {
+            rom,
+            chip,
+            options,
+            keymap,
+            record_movie: cli.record_movie,
+            play_movie: cli.play_movie,
+            #[cfg(feature = "scripting")]
+            script: cli.script,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn command_line_over_config() {
+        let config: Config = toml::from_str(r#"
+            rom = "roms/p1.bin"
+            speed = 4.0
+            panels = ["lcd", "registers"]
+            palette = "gray"
+
+            [keys]
+            frame-advance = "space"
+        "#).unwrap();
+        let cli = Cli::parse_from(["rustchi", "--speed", "2", "--chip", "e0c6s48"]);
+
+        let settings = Settings::merge(cli, config).unwrap();
+        assert_eq!(settings.rom, PathBuf::from("roms/p1.bin"));
+        assert_eq!(settings.chip.name, "E0C6S48");
+        assert_eq!(settings.options.speed, 2.0);
+        assert_eq!(settings.options.save, PathBuf::from("roms/p1.sav"));
+        assert_eq!(settings.options.palette, Palette::Gray);
+        assert_eq!(settings.options.panels, Panels { lcd: true, disassembler: false, registers: true, memory: false });
+
+        assert_eq!(settings.keymap.action(KeyCode::Char(' ')), Some(Action::FrameAdvance));
+        assert_eq!(settings.keymap.action(KeyCode::Char('n')), None);
+
+        assert!(toml::from_str::<Config>("sped = 2.0").is_err());
+    }
+}
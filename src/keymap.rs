@@ -0,0 +1,205 @@
+use crossterm::event::KeyCode;
+use rustchi_core::input::Button;
+
+/// What a key does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Button(Button),
+    Pause,
+    /// Executes one instruction while paused.
+    Step,
+    /// Emulates one frame and pauses.
+    FrameAdvance,
+    SaveState,
+    LoadState,
+    Screenshot,
+    Record,
+    Turbo,
+    Reset,
+    ToggleDebugger,
+    ToggleLcd,
+    LowBattery,
+    Quit,
+}
+
+impl Action {
+    const ALL: [Action; 16] = [
+        Action::Button(Button::A),
+        Action::Button(Button::B),
+        Action::Button(Button::C),
+        Action::Pause,
+        Action::Step,
+        Action::FrameAdvance,
+        Action::SaveState,
+        Action::LoadState,
+        Action::Screenshot,
+        Action::Record,
+        Action::Turbo,
+        Action::Reset,
+        Action::ToggleDebugger,
+        Action::ToggleLcd,
+        Action::LowBattery,
+        Action::Quit,
+    ];
+
+    /// Name in the `[keys]` table of the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Button(Button::A) => "a",
+            Action::Button(Button::B) => "b",
+            Action::Button(Button::C) => "c",
+            Action::Pause => "pause",
+            Action::Step => "step",
+            Action::FrameAdvance => "frame-advance",
+            Action::SaveState => "save-state",
+            Action::LoadState => "load-state",
+            Action::Screenshot => "screenshot",
+            Action::Record => "record",
+            Action::Turbo => "turbo",
+            Action::Reset => "reset",
+            Action::ToggleDebugger => "toggle-debugger",
+            Action::ToggleLcd => "toggle-lcd",
+            Action::LowBattery => "low-battery",
+            Action::Quit => "quit",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Action::Button(Button::A) => "A button",
+            Action::Button(Button::B) => "B button",
+            Action::Button(Button::C) => "C button",
+            Action::Pause => "Pause/resume",
+            Action::Step => "Step",
+            Action::FrameAdvance => "Next frame",
+            Action::SaveState => "Save",
+            Action::LoadState => "Load",
+            Action::Screenshot => "Screenshot",
+            Action::Record => "Record",
+            Action::Turbo => "Turbo",
+            Action::Reset => "Reset",
+            Action::ToggleDebugger => "Debugger",
+            Action::ToggleLcd => "LCD",
+            Action::LowBattery => "Low battery",
+            Action::Quit => "Quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// Keys bound to actions, one key per action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: Vec<(Action, KeyCode)>,
+}
+
+impl Keymap {
+    /// Binds `key` to `action`, replacing the action's previous key and unbinding whatever used `key`.
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        self.bindings.retain(|(bound_action, bound_key)| *bound_action != action && *bound_key != key);
+        self.bindings.push((action, key));
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        let key = match key {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            key => key,
+        };
+        self.bindings.iter().find(|(_, bound)| *bound == key).map(|(action, _)| *action)
+    }
+
+    /// One line listing the keys, in the order of `Action::ALL`.
+    pub fn help(&self) -> String {
+        Action::ALL.into_iter().filter_map(|action| {
+            let (_, key) = self.bindings.iter().find(|(bound, _)| *bound == action)?;
+            Some(format!("[{}] {}", key_name(*key), action.label()))
+        }).collect::<Vec<_>>().join("  ")
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Action::Button(Button::A), KeyCode::Char('a')),
+                (Action::Button(Button::B), KeyCode::Char('s')),
+                (Action::Button(Button::C), KeyCode::Char('d')),
+                (Action::Pause, KeyCode::Char('p')),
+                (Action::Step, KeyCode::Char('.')),
+                (Action::FrameAdvance, KeyCode::Char('n')),
+                (Action::SaveState, KeyCode::F(5)),
+                (Action::LoadState, KeyCode::F(9)),
+                (Action::Screenshot, KeyCode::Char('x')),
+                (Action::Record, KeyCode::Char('r')),
+                (Action::Turbo, KeyCode::Char('t')),
+                (Action::Reset, KeyCode::F(12)),
+                (Action::ToggleDebugger, KeyCode::Char('g')),
+                (Action::ToggleLcd, KeyCode::Char('l')),
+                (Action::LowBattery, KeyCode::Char('b')),
+                (Action::Quit, KeyCode::Char('q')),
+            ],
+        }
+    }
+}
+
+/// Parses a single character, `F1` to `F12`, or one of `space`, `tab`, `enter`, `esc`,
+/// `backspace`, `up`, `down`, `left` and `right`.
+pub fn parse_key(name: &str) -> Result<KeyCode, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c.to_ascii_lowercase()));
+    }
+
+    let lower = name.to_ascii_lowercase();
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()).filter(|n| (1..=12).contains(n)) {
+        return Ok(KeyCode::F(n));
+    }
+
+    match lower.as_str() {
+        "space" => Ok(KeyCode::Char(' ')),
+        "tab" => Ok(KeyCode::Tab),
+        "enter" => Ok(KeyCode::Enter),
+        "esc" => Ok(KeyCode::Esc),
+        "backspace" => Ok(KeyCode::Backspace),
+        "up" => Ok(KeyCode::Up),
+        "down" => Ok(KeyCode::Down),
+        "left" => Ok(KeyCode::Left),
+        "right" => Ok(KeyCode::Right),
+        _ => Err(format!("unknown key `{}`", name)),
+    }
+}
+
+fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_ascii_uppercase().to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        key => format!("{:?}", key),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rebind() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.action(KeyCode::Char('A')), Some(Action::Button(Button::A)));
+
+        keymap.bind(Action::Button(Button::A), parse_key("j").unwrap());
+        keymap.bind(Action::Turbo, parse_key("s").unwrap());
+        assert_eq!(keymap.action(KeyCode::Char('a')), None);
+        assert_eq!(keymap.action(KeyCode::Char('j')), Some(Action::Button(Button::A)));
+        assert_eq!(keymap.action(KeyCode::Char('s')), Some(Action::Turbo));
+        assert_eq!(keymap.action(KeyCode::Char('t')), None);
+        assert!(!keymap.help().contains("B button"));
+
+        assert_eq!(parse_key("F5"), Ok(KeyCode::F(5)));
+        assert_eq!(parse_key("space"), Ok(KeyCode::Char(' ')));
+        assert!(parse_key("F13").is_err());
+    }
+}
@@ -1,16 +1,21 @@
+mod config;
+mod keymap;
+
 use std::fs;
-use rustchi_core::{
-    interpreter::Interpreter,
-    input::Button,
-};
+use rustchi_core::{interpreter::Interpreter, movie::Movie};
 use rustchi_terminal::{FFI, Terminal};
+use keymap::Action;
 
 use crossterm::{
     event,
     event::Event,
-    event::KeyCode,
     event::KeyEvent,
     event::KeyEventKind,
+    event::KeyModifiers,
+    event::KeyboardEnhancementFlags,
+    event::MouseButton,
+    event::MouseEvent,
+    event::MouseEventKind,
     QueueableCommand,
     cursor,
     style,
@@ -18,7 +23,15 @@ use crossterm::{
     ExecutableCommand
 };
 
-use std::io::{Write, stdout};
+use std::io::{Stdout, Write, stdout};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// Whether the terminal was asked to report key releases, to undo it on exit
+static KEYBOARD_ENHANCED: AtomicBool = AtomicBool::new(false);
+
+// How long a status message replaces the keys on the first line
+const STATUS_DURATION: Duration = Duration::from_secs(3);
 
 struct ConsoleFFI;
 
@@ -39,7 +52,6 @@ impl FFI for ConsoleFFI {
 }
 
 fn main() -> std::io::Result<()> {
-    terminal::enable_raw_mode()?;
 
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
@@ -47,26 +59,68 @@ fn main() -> std::io::Result<()> {
         default_panic(info);
     }));
 
-    println!("Loading rom...");
+    let settings = config::Settings::from_args().unwrap_or_else(|error| exit_with(&error));
+
+    println!("Loading {}...", settings.rom.display());
+
+    let bytes = fs::read(&settings.rom).unwrap_or_else(|error| exit_with(&format!("can't read {}: {}", settings.rom.display(), error)));
+    let interpreter = Interpreter::load(&bytes, settings.chip).unwrap_or_else(|error| exit_with(&format!("can't load {}: {}", settings.rom.display(), error)));
+
+    println!("Loaded {} words ({}).\n", interpreter.rom.words.len(), interpreter.rom.format);
 
-    let bytes = fs::read("www/rom.bin").unwrap();
-    let interpreter = Interpreter::load(bytes);
+    let keymap = settings.keymap;
+    let mut gui = Terminal::new(ConsoleFFI::new(), interpreter, settings.options);
 
-    println!("Loaded {} bytes.\n", interpreter.rom.len());
+    if let Some(path) = &settings.play_movie {
+        let bytes = fs::read(path).unwrap_or_else(|error| exit_with(&format!("can't read {}: {}", path.display(), error)));
+        Movie::from_bytes(&bytes)
+            .and_then(|movie| gui.play_movie(movie))
+            .unwrap_or_else(|error| exit_with(&format!("can't play {}: {}", path.display(), error)));
+    }
+    if settings.record_movie.is_some() {
+        gui.record_movie();
+    }
+
+    #[cfg(feature = "scripting")]
+    if let Some(path) = &settings.script {
+        let source = fs::read_to_string(path).unwrap_or_else(|error| exit_with(&format!("can't read {}: {}", path.display(), error)));
+        gui.load_script(&source).unwrap_or_else(|error| exit_with(&format!("{}: {}", path.display(), error)));
+    }
 
-    let mut gui = Terminal::new(ConsoleFFI::new(), interpreter);
+    if gui.is_headless() {
+        gui.run_headless()?;
+        return save_movie(&mut gui, settings.record_movie);
+    }
+
+    terminal::enable_raw_mode()?;
 
     let mut stdout = stdout();
 
+    // Most terminals only report key presses, so without the kitty keyboard protocol
+    // buttons are tapped, or latched with Shift, instead of held
+    let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced {
+        stdout.queue(event::PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        KEYBOARD_ENHANCED.store(true, Ordering::Relaxed);
+    }
+
     let mut paused = false;
+    // Released when the mouse button is, even if the pointer left the label
+    let mut clicked = None;
+    let help = format!("{}{}", keymap.help(), if enhanced { "" } else { "  [Shift] Hold button" });
+    let mut status: Option<Instant> = None;
 
     stdout
         .queue(cursor::Hide)?
-        .queue(terminal::Clear(terminal::ClearType::All))?
-        .queue(cursor::MoveTo(0, 0))?
-        .queue(style::Print("[A] A button  [S] B button  [D] C button                                                   [P] Pause/resume  [Q] Quit"))?;
+        .queue(event::EnableMouseCapture)?
+        .queue(terminal::Clear(terminal::ClearType::All))?;
+    print_first_line(&mut stdout, &help)?;
 
     loop {
+        if status.is_some_and(|shown| shown.elapsed() >= STATUS_DURATION) {
+            status = None;
+            print_first_line(&mut stdout, &help)?;
+        }
         stdout.queue(cursor::MoveTo(0, 1))?;
 
         if !paused {
@@ -75,26 +129,89 @@ fn main() -> std::io::Result<()> {
 
         stdout.flush()?;
 
-        if let Ok(true) = event::poll(std::time::Duration::from_secs(0)) {
-            let event = event::read()?;
-
-            match event {
-                Event::Key(KeyEvent {code: KeyCode::Char('q'), kind: KeyEventKind::Press, ..}) =>
-                    break,
-                Event::Key(KeyEvent {code: KeyCode::Char('p'), kind: KeyEventKind::Press, ..}) =>
-                    paused = !paused,
-                Event::Key(KeyEvent {code: KeyCode::Char('a'), kind: KeyEventKind::Press, ..}) =>
-                    gui.press_button(Button::A),
-                Event::Key(KeyEvent {code: KeyCode::Char('a'), kind: KeyEventKind::Release, ..}) =>
-                    gui.release_button(Button::A),
-                Event::Key(KeyEvent {code: KeyCode::Char('s'), kind: KeyEventKind::Press, ..}) =>
-                    gui.press_button(Button::B),
-                Event::Key(KeyEvent {code: KeyCode::Char('s'), kind: KeyEventKind::Release, ..}) =>
-                    gui.release_button(Button::B),
-                Event::Key(KeyEvent {code: KeyCode::Char('d'), kind: KeyEventKind::Press, ..}) =>
-                    gui.press_button(Button::C),
-                Event::Key(KeyEvent {code: KeyCode::Char('d'), kind: KeyEventKind::Release, ..}) =>
-                    gui.release_button(Button::C),
+        if let Ok(true) = event::poll(Duration::from_secs(0)) {
+            match event::read()? {
+                Event::Key(KeyEvent { code, kind, modifiers, .. }) => match (keymap.action(code), kind) {
+                    (Some(Action::Button(button)), KeyEventKind::Press) if enhanced =>
+                        gui.press_button(button),
+                    (Some(Action::Button(button)), KeyEventKind::Release) =>
+                        gui.release_button(button),
+                    (Some(Action::Button(button)), KeyEventKind::Press) if modifiers.contains(KeyModifiers::SHIFT) => {
+                        if gui.is_button_pressed(button) {
+                            gui.release_button(button);
+                        } else {
+                            gui.press_button(button);
+                        }
+                    },
+                    (Some(Action::Button(button)), KeyEventKind::Press) =>
+                        gui.tap_button(button),
+                    (Some(action), KeyEventKind::Press) => match action {
+                        Action::Quit => break,
+                        Action::Pause => paused = !paused,
+                        Action::Step => {
+                            paused = true;
+                            gui.step_instruction();
+                        },
+                        Action::FrameAdvance => {
+                            paused = true;
+                            gui.advance_frame();
+                        },
+                        Action::SaveState => {
+                            let message = match gui.save_state() {
+                                Ok(path) => format!("Saved state to {}", path.display()),
+                                Err(error) => format!("Can't save state: {}", error),
+                            };
+                            show_status(&mut stdout, &mut status, &message)?;
+                        },
+                        Action::LoadState => {
+                            let message = match gui.load_state() {
+                                Ok(()) => "Loaded state".to_string(),
+                                Err(error) => format!("Can't load state: {}", error),
+                            };
+                            show_status(&mut stdout, &mut status, &message)?;
+                        },
+                        Action::Screenshot => {
+                            let message = match gui.screenshot() {
+                                Ok(path) => format!("Saved screenshot to {}", path.display()),
+                                Err(error) => format!("Can't save screenshot: {}", error),
+                            };
+                            show_status(&mut stdout, &mut status, &message)?;
+                        },
+                        Action::Record => {
+                            let message = match gui.toggle_recording() {
+                                Ok(None) => "Recording".to_string(),
+                                Ok(Some(path)) => format!("Saved recording to {}", path.display()),
+                                Err(error) => format!("Can't save recording: {}", error),
+                            };
+                            show_status(&mut stdout, &mut status, &message)?;
+                        },
+                        Action::Turbo => gui.toggle_turbo(),
+                        Action::Reset => gui.reset(),
+                        Action::LowBattery => gui.toggle_low_battery(),
+                        Action::ToggleDebugger => {
+                            gui.toggle_debugger();
+                            stdout.queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+                        },
+                        Action::ToggleLcd => {
+                            gui.toggle_lcd();
+                            stdout.queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+                        },
+                        Action::Button(_) => (),
+                    },
+                    _ => (),
+                },
+                Event::Mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column, row, .. }) => {
+                    // The panels start below the line listing the keys
+                    clicked = row.checked_sub(1).and_then(|row| gui.button_at(column.into(), row.into()));
+                    if let Some(button) = clicked {
+                        gui.press_button(button);
+                    }
+                },
+                Event::Mouse(MouseEvent { kind: MouseEventKind::Up(MouseButton::Left), .. }) => {
+                    if let Some(button) = clicked.take() {
+                        gui.release_button(button);
+                    }
+                },
                 _ => (),
             }
         }
@@ -108,11 +225,43 @@ fn main() -> std::io::Result<()> {
     }
 
     restore_terminal();
+    save_movie(&mut gui, settings.record_movie)
+}
+
+fn save_movie<T: FFI>(gui: &mut Terminal<T>, path: Option<std::path::PathBuf>) -> std::io::Result<()> {
+    match (gui.finish_movie(), path) {
+        (Some(movie), Some(path)) => fs::write(path, movie.to_bytes()),
+        _ => Ok(()),
+    }
+}
+
+// The line above the panels, listing the keys or telling how an action went
+fn print_first_line(stdout: &mut Stdout, text: &str) -> std::io::Result<()> {
+    stdout
+        .queue(cursor::MoveTo(0, 0))?
+        .queue(terminal::Clear(terminal::ClearType::CurrentLine))?
+        .queue(style::Print(text))?;
+    Ok(())
+}
+
+// Replaces the keys with `message` for `STATUS_DURATION`
+fn show_status(stdout: &mut Stdout, status: &mut Option<Instant>, message: &str) -> std::io::Result<()> {
+    print_first_line(stdout, message)?;
+    *status = Some(Instant::now());
     Ok(())
 }
 
 fn restore_terminal() {
+    if KEYBOARD_ENHANCED.load(Ordering::Relaxed) {
+        _ = stdout().execute(event::PopKeyboardEnhancementFlags);
+    }
+    _ = stdout().execute(event::DisableMouseCapture);
     _ = terminal::disable_raw_mode();
     _ = stdout().execute(cursor::Show);
     println!();
 }
+
+fn exit_with(error: &str) -> ! {
+    eprintln!("error: {}", error);
+    std::process::exit(1);
+}